@@ -0,0 +1,716 @@
+use crate::error::{AppError, Result};
+use crate::formats::{self, ExportContext};
+use crate::manifest::{self, Scalar};
+use crate::models::{Environment, parse_duration};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use yaml_rust2::YamlLoader;
+
+/// Output format that writes each variable to its own file in a directory,
+/// like Kubernetes secret volumes.
+const FILES_FORMAT: &str = "files";
+const DEFAULT_WAIT: &str = "5m";
+/// Added to the blocking-query wait before a request counts as hung.
+const REQUEST_SLACK: Duration = Duration::from_secs(30);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// What `rusty agent` keeps on disk, read from `rusty-agent.yaml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentConfig {
+    /// Base URL of the rusty server, e.g. `http://127.0.0.1:8080`
+    pub server: Option<String>,
+    /// File the latest copy of each environment is kept in, so outputs are
+    /// rendered on start even while the server is unreachable
+    pub cache: Option<PathBuf>,
+    /// How long each blocking query waits for a change, e.g. `5m`
+    pub wait: Option<String>,
+    pub outputs: Vec<Output>,
+    #[serde(default)]
+    pub reload: Reload,
+}
+
+/// One rendered file, or directory for the `files` format.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    pub project: String,
+    pub environment: String,
+    /// Any export format, or `files` for one file per variable in `path`
+    #[serde(default = "default_format")]
+    pub format: String,
+    pub path: PathBuf,
+    /// Format options, as with `env export -o`
+    #[serde(default)]
+    pub options: BTreeMap<String, Scalar>,
+}
+
+/// How to tell the app its files changed. Runs once per batch of changes.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Reload {
+    /// Signal such as `HUP`, sent to `pid` or the process in `pid_file`
+    pub signal: Option<String>,
+    pub pid: Option<u32>,
+    pub pid_file: Option<PathBuf>,
+    /// Shell command to run, e.g. `systemctl reload app`
+    pub command: Option<String>,
+}
+
+fn default_format() -> String {
+    "dotenv".to_string()
+}
+
+impl AgentConfig {
+    pub fn parse(input: &str) -> Result<Self> {
+        let docs = YamlLoader::load_from_str(input)
+            .map_err(|e| AppError::ConfigError(format!("invalid YAML: {}", e)))?;
+        let value = docs
+            .first()
+            .map(manifest::to_json)
+            .transpose()?
+            .unwrap_or_default();
+        let config: Self = serde_json::from_value(value)
+            .map_err(|e| AppError::ConfigError(format!("invalid agent config: {}", e)))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.outputs.is_empty() {
+            return Err(AppError::ConfigError("no outputs to render".to_string()));
+        }
+        for output in &self.outputs {
+            if output.format != FILES_FORMAT {
+                formats::registry().check_export_options(&output.format, &output.options())?;
+            } else if !output.options.is_empty() {
+                return Err(AppError::ConfigError(format!(
+                    "{}: the files format takes no options",
+                    output.path.display()
+                )));
+            }
+        }
+        self.wait()?;
+
+        let reload = &self.reload;
+        if let Some(signal) = &reload.signal {
+            signal_number(signal)
+                .ok_or_else(|| AppError::ConfigError(format!("unknown signal {}", signal)))?;
+            if reload.pid.is_none() == reload.pid_file.is_none() {
+                return Err(AppError::ConfigError(
+                    "reload.signal needs exactly one of pid and pid_file".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn wait(&self) -> Result<Duration> {
+        let wait = self.wait.as_deref().unwrap_or(DEFAULT_WAIT);
+        parse_duration(wait)
+            .ok_or_else(|| AppError::ConfigError(format!("invalid wait duration: {}", wait)))
+    }
+
+    /// Distinct `(project, environment)` pairs the outputs read from.
+    fn targets(&self) -> Vec<(String, String)> {
+        let mut targets: Vec<_> = self
+            .outputs
+            .iter()
+            .map(|output| (output.project.clone(), output.environment.clone()))
+            .collect();
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+}
+
+/// The last copy of an environment fetched from the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Cached {
+    index: u64,
+    variables: Environment,
+}
+
+fn cache_key(project: &str, env: &str) -> String {
+    format!("{}/{}", project, env)
+}
+
+struct Update {
+    project: String,
+    environment: String,
+    cached: Cached,
+}
+
+/// Reads environments from a rusty server with blocking queries.
+#[derive(Clone)]
+struct Client {
+    http: reqwest::Client,
+    server: reqwest::Url,
+    wait: Duration,
+}
+
+impl Client {
+    fn new(server: &str, wait: Duration) -> Result<Self> {
+        let server = reqwest::Url::parse(server)
+            .map_err(|e| AppError::ConfigError(format!("invalid server URL {}: {}", server, e)))?;
+        let http = reqwest::Client::builder()
+            .timeout(wait + REQUEST_SLACK)
+            .user_agent(concat!("rusty-agent/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("the HTTP client configuration is valid");
+        Ok(Self { http, server, wait })
+    }
+
+    /// The merged environment, once its index passes `index` or the wait
+    /// runs out; without `index`, right away.
+    async fn fetch(&self, project: &str, env: &str, index: Option<u64>) -> Result<Cached> {
+        let mut url = self.server.clone();
+        url.path_segments_mut()
+            .map_err(|_| AppError::ConfigError(format!("invalid server URL {}", self.server)))?
+            .pop_if_empty()
+            .extend(["api", "projects", project, "envs", env]);
+        if let Some(index) = index {
+            url.query_pairs_mut()
+                .append_pair("index", &index.to_string())
+                .append_pair("wait", &format!("{}s", self.wait.as_secs()));
+        }
+
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| AppError::ServerError(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            let message = body["error"].as_str().unwrap_or(status.as_str());
+            return Err(AppError::ServerError(message.to_string()));
+        }
+
+        let index = response
+            .headers()
+            .get("x-rusty-index")
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .unwrap_or(0);
+        let variables = response
+            .json()
+            .await
+            .map_err(|e| AppError::ServerError(e.to_string()))?;
+        Ok(Cached { index, variables })
+    }
+
+    /// Sends an update whenever the environment changes, retrying failed
+    /// requests with exponential backoff.
+    async fn watch(
+        self,
+        project: String,
+        environment: String,
+        mut index: Option<u64>,
+        updates: mpsc::Sender<Update>,
+    ) {
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            match self.fetch(&project, &environment, index).await {
+                Ok(cached) => {
+                    backoff = INITIAL_BACKOFF;
+                    // Equal when the wait ran out; lower if the server's data was replaced
+                    if index == Some(cached.index) {
+                        continue;
+                    }
+                    index = Some(cached.index);
+                    let update = Update {
+                        project: project.clone(),
+                        environment: environment.clone(),
+                        cached,
+                    };
+                    if updates.send(update).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    eprintln!(
+                        "⚠ {}/{}: {}, retrying in {}s",
+                        project,
+                        environment,
+                        e,
+                        backoff.as_secs()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the configured outputs in sync with the server.
+pub struct Agent {
+    config: AgentConfig,
+    client: Client,
+    cache: BTreeMap<String, Cached>,
+}
+
+impl Agent {
+    pub fn new(config: AgentConfig, server: &str) -> Result<Self> {
+        let client = Client::new(server, config.wait()?)?;
+        let cache = match &config.cache {
+            Some(path) => load_cache(path),
+            None => BTreeMap::new(),
+        };
+        Ok(Self {
+            config,
+            client,
+            cache,
+        })
+    }
+
+    /// Renders every output from the server once.
+    pub async fn render_once(&mut self) -> Result<()> {
+        let mut changed = false;
+        for (project, environment) in self.config.targets() {
+            let cached = self.client.fetch(&project, &environment, None).await?;
+            let (target_changed, ok) = self.update(&project, &environment, cached);
+            if !ok {
+                return Err(AppError::ConfigError(format!(
+                    "failed to render {}/{}",
+                    project, environment
+                )));
+            }
+            changed |= target_changed;
+        }
+        self.save_cache();
+        if changed {
+            self.config.reload.run().await;
+        }
+        Ok(())
+    }
+
+    /// Renders the cached copies, then re-renders whenever the server's
+    /// copy changes, until interrupted.
+    pub async fn run(mut self) -> Result<()> {
+        let mut changed = false;
+        let cached: Vec<_> = self
+            .config
+            .targets()
+            .into_iter()
+            .filter_map(|(project, env)| {
+                let cached = self.cache.get(&cache_key(&project, &env))?.clone();
+                Some((project, env, cached))
+            })
+            .collect();
+        for (project, environment, cached) in cached {
+            changed |= self.update(&project, &environment, cached).0;
+        }
+        if changed {
+            self.config.reload.run().await;
+        }
+
+        let (updates, mut received) = mpsc::channel(16);
+        for (project, environment) in self.config.targets() {
+            let index = self
+                .cache
+                .get(&cache_key(&project, &environment))
+                .map(|cached| cached.index);
+            tokio::spawn(
+                self.client
+                    .clone()
+                    .watch(project, environment, index, updates.clone()),
+            );
+        }
+        drop(updates);
+
+        loop {
+            let update = tokio::select! {
+                update = received.recv() => update,
+                _ = shutdown() => return Ok(()),
+            };
+            let Some(update) = update else {
+                return Ok(());
+            };
+
+            // Changes to several environments at once reload the app once
+            let mut changed = self.apply(update);
+            while let Ok(update) = received.try_recv() {
+                changed |= self.apply(update);
+            }
+            self.save_cache();
+            if changed {
+                self.config.reload.run().await;
+            }
+        }
+    }
+
+    fn apply(&mut self, update: Update) -> bool {
+        self.update(&update.project, &update.environment, update.cached)
+            .0
+    }
+
+    /// Caches `cached` and renders the outputs reading from it. Returns
+    /// whether any file changed and whether every output rendered.
+    fn update(&mut self, project: &str, environment: &str, cached: Cached) -> (bool, bool) {
+        let previous = self
+            .cache
+            .insert(cache_key(project, environment), cached.clone());
+        let previous = previous.as_ref().map(|previous| &previous.variables);
+
+        let (mut changed, mut ok) = (false, true);
+        for output in &self.config.outputs {
+            if output.project != project || output.environment != environment {
+                continue;
+            }
+            match output.render(&cached.variables, previous) {
+                Ok(true) => {
+                    println!(
+                        "✓ Rendered {}/{} to {}",
+                        project,
+                        environment,
+                        output.path.display()
+                    );
+                    changed = true;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    eprintln!("✗ Failed to render {}: {}", output.path.display(), e);
+                    ok = false;
+                }
+            }
+        }
+        (changed, ok)
+    }
+
+    fn save_cache(&self) {
+        let Some(path) = &self.config.cache else {
+            return;
+        };
+        let result = serde_json::to_vec_pretty(&self.cache)
+            .map_err(AppError::from)
+            .and_then(|json| Ok(write_atomic(path, &json)?));
+        if let Err(e) = result {
+            eprintln!("⚠ Failed to save cache {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// A missing or unreadable cache starts empty.
+fn load_cache(path: &Path) -> BTreeMap<String, Cached> {
+    let Ok(json) = fs::read(path) else {
+        return BTreeMap::new();
+    };
+    serde_json::from_slice(&json).unwrap_or_else(|e| {
+        eprintln!("⚠ Ignoring unreadable cache {}: {}", path.display(), e);
+        BTreeMap::new()
+    })
+}
+
+impl Output {
+    fn options(&self) -> BTreeMap<String, String> {
+        self.options
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string()))
+            .collect()
+    }
+
+    /// Writes the files whose content differs from `variables`; returns
+    /// whether any did. `previous` says which per-key files to remove.
+    fn render(&self, variables: &Environment, previous: Option<&Environment>) -> Result<bool> {
+        if self.format != FILES_FORMAT {
+            let ctx = ExportContext {
+                project: self.project.clone(),
+                environment: self.environment.clone(),
+                options: self.options(),
+            };
+            let rendered = formats::registry().export(&self.format, variables, &ctx)?;
+            return write_if_changed(&self.path, rendered.as_bytes());
+        }
+
+        fs::create_dir_all(&self.path)?;
+        let mut changed = false;
+        for (key, var) in variables {
+            changed |= write_if_changed(&self.key_path(key)?, var.value.as_bytes())?;
+        }
+        for key in previous.into_iter().flat_map(|previous| previous.keys()) {
+            if !variables.contains_key(key) {
+                match fs::remove_file(self.key_path(key)?) {
+                    Ok(()) => changed = true,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    fn key_path(&self, key: &str) -> Result<PathBuf> {
+        if key.is_empty() || key.starts_with('.') || key.contains(['/', '\\']) {
+            return Err(AppError::InvalidInput(format!(
+                "{} can't be used as a file name",
+                key
+            )));
+        }
+        Ok(self.path.join(key))
+    }
+}
+
+fn write_if_changed(path: &Path, contents: &[u8]) -> Result<bool> {
+    if fs::read(path).is_ok_and(|current| current == contents) {
+        return Ok(false);
+    }
+    write_atomic(path, contents)?;
+    Ok(true)
+}
+
+/// Writes `contents` to a temporary file readable only by its owner, then
+/// renames it over `path`, so readers never see a partial file.
+fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not a file path", path.display()),
+        )
+    })?;
+    let temp = dir.join(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let _ = fs::remove_file(&temp);
+    let result = options.open(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    let result = result.and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+impl Reload {
+    /// Signals the app and runs the reload command. Failures are reported
+    /// but don't stop the agent.
+    async fn run(&self) {
+        if let Some(signal) = &self.signal
+            && let Err(e) = self.send_signal(signal)
+        {
+            eprintln!("✗ Failed to send SIG{}: {}", signal_name(signal), e);
+        }
+
+        if let Some(command) = &self.command {
+            match shell_command(command).status().await {
+                Ok(status) if status.success() => println!("↻ Ran {}", command),
+                Ok(status) => eprintln!("✗ {} exited with {}", command, status),
+                Err(e) => eprintln!("✗ Failed to run {}: {}", command, e),
+            }
+        }
+    }
+
+    fn send_signal(&self, signal: &str) -> Result<()> {
+        let pid = match (self.pid, &self.pid_file) {
+            (Some(pid), _) => pid,
+            (None, Some(path)) => fs::read_to_string(path)?.trim().parse().map_err(|_| {
+                AppError::InvalidInput(format!("{} doesn't hold a process ID", path.display()))
+            })?,
+            (None, None) => unreachable!("validated by AgentConfig::parse"),
+        };
+        let signum = signal_number(signal).expect("validated by AgentConfig::parse");
+        kill(pid, signum)?;
+        println!("↻ Sent SIG{} to {}", signal_name(signal), pid);
+        Ok(())
+    }
+}
+
+fn signal_name(signal: &str) -> String {
+    let signal = signal.to_ascii_uppercase();
+    signal.strip_prefix("SIG").unwrap_or(&signal).to_string()
+}
+
+#[cfg(unix)]
+fn signal_number(signal: &str) -> Option<i32> {
+    Some(match signal_name(signal).as_str() {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "TERM" => libc::SIGTERM,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        _ => return None,
+    })
+}
+
+#[cfg(not(unix))]
+fn signal_number(_signal: &str) -> Option<i32> {
+    None
+}
+
+#[cfg(unix)]
+fn kill(pid: u32, signum: i32) -> Result<()> {
+    // SAFETY: `kill` has no memory-safety preconditions.
+    if unsafe { libc::kill(pid as libc::pid_t, signum) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn kill(_pid: u32, _signum: i32) -> Result<()> {
+    unreachable!("signals are rejected by AgentConfig::parse")
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(not(unix))]
+fn shell_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+/// Resolves on Ctrl-C, or `SIGTERM` on unix.
+async fn shutdown() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvVariable;
+
+    fn environment(vars: &[(&str, &str)]) -> Environment {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), EnvVariable::new(value.to_string(), false)))
+            .collect()
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rusty-agent-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_and_validates_configs() {
+        let config = AgentConfig::parse(
+            r#"
+server: http://127.0.0.1:8080
+outputs:
+  - project: app
+    environment: production
+    path: /run/app/.env
+  - project: app
+    environment: production
+    format: json
+    path: /run/app/env.json
+    options:
+      separator: __
+reload:
+  signal: SIGHUP
+  pid_file: /run/app.pid
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.outputs[0].format, "dotenv");
+        assert_eq!(config.targets().len(), 1);
+
+        let invalid = |yaml: &str| AgentConfig::parse(yaml).is_err();
+        assert!(invalid("outputs: []"));
+        assert!(invalid(
+            "outputs: [{project: a, environment: b, path: x, format: nope}]"
+        ));
+        assert!(invalid(
+            "outputs: [{project: a, environment: b, path: x, format: json, options: {indent: 2}}]"
+        ));
+        assert!(invalid(
+            "outputs: [{project: a, environment: b, path: x, format: files, options: {separator: _}}]"
+        ));
+        assert!(invalid(
+            "outputs: [{project: a, environment: b, path: x}]\nreload: {signal: HUP}"
+        ));
+        assert!(invalid(
+            "outputs: [{project: a, environment: b, path: x}]\nreload: {signal: NOPE, pid: 1}"
+        ));
+    }
+
+    #[test]
+    fn renders_files_atomically_and_only_on_change() {
+        let dir = temp_dir();
+        let output = Output {
+            project: "app".to_string(),
+            environment: "production".to_string(),
+            format: "dotenv".to_string(),
+            path: dir.join(".env"),
+            options: BTreeMap::new(),
+        };
+        let env = environment(&[("PORT", "8080")]);
+
+        assert!(output.render(&env, None).unwrap());
+        assert!(!output.render(&env, Some(&env)).unwrap());
+        assert!(fs::read_to_string(&output.path).unwrap().contains("PORT"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&output.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renders_one_file_per_key() {
+        let dir = temp_dir();
+        let output = Output {
+            project: "app".to_string(),
+            environment: "production".to_string(),
+            format: FILES_FORMAT.to_string(),
+            path: dir.join("secrets"),
+            options: BTreeMap::new(),
+        };
+        let before = environment(&[("A", "1"), ("B", "2")]);
+        let after = environment(&[("A", "1")]);
+
+        assert!(output.render(&before, None).unwrap());
+        assert_eq!(fs::read_to_string(output.path.join("B")).unwrap(), "2");
+        assert!(output.render(&after, Some(&before)).unwrap());
+        assert!(!output.path.join("B").exists());
+        assert!(output.render(&environment(&[("../x", "1")]), None).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+use crate::error::{AppError, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the per-directory file that binds a directory tree to a project.
+pub const BINDING_FILE: &str = ".rusty.toml";
+
+/// Contents of a `.rusty.toml`, e.g.
+///
+/// ```toml
+/// project = "myapp"
+/// environment = "development"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectBinding {
+    pub project: String,
+    pub environment: Option<String>,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl ProjectBinding {
+    /// Walks up from `start` and returns the nearest binding, if any.
+    pub fn discover(start: &Path) -> Result<Option<Self>> {
+        for dir in start.ancestors() {
+            let path = dir.join(BINDING_FILE);
+            if path.is_file() {
+                return Self::load(&path).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut binding: ProjectBinding = toml::from_str(&contents)
+            .map_err(|e| AppError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        binding.path = path.to_path_buf();
+        Ok(binding)
+    }
+
+    /// Finds the binding for `path`, which may be a `.rusty.toml` itself or
+    /// a directory to search upwards from.
+    pub fn locate(path: &Path) -> Result<Self> {
+        if path.is_file() {
+            return Self::load(path);
+        }
+
+        Self::discover(path)?.ok_or_else(|| {
+            AppError::InvalidInput(format!(
+                "No {} found in {} or its parents",
+                BINDING_FILE,
+                path.display()
+            ))
+        })
+    }
+}
+
+/// Project and environment a CLI command applies to when they are not given
+/// explicitly: the nearest `.rusty.toml` first, then the configured defaults.
+pub struct Scope {
+    binding: Option<ProjectBinding>,
+    default_environment: String,
+    /// Existing projects, to tell an omitted project from a mistyped call
+    projects: Vec<String>,
+}
+
+impl Scope {
+    pub fn new(binding: Option<ProjectBinding>, default_environment: String) -> Self {
+        Self {
+            binding,
+            default_environment,
+            projects: Vec::new(),
+        }
+    }
+
+    /// Makes [`split_args`](Self::split_args) refuse calls whose first
+    /// argument names one of `projects` other than the bound one.
+    pub fn with_projects(mut self, projects: Vec<String>) -> Self {
+        self.projects = projects;
+        self
+    }
+
+    pub fn discover(default_environment: String) -> Result<Self> {
+        let cwd = std::env::current_dir()?;
+        Ok(Self::new(
+            ProjectBinding::discover(&cwd)?,
+            default_environment,
+        ))
+    }
+
+    pub fn project(&self, project: Option<String>) -> Result<String> {
+        project
+            .or_else(|| self.binding.as_ref().map(|b| b.project.clone()))
+            .ok_or_else(|| {
+                AppError::InvalidInput(format!(
+                    "No project given and no {} found in this directory or its parents",
+                    BINDING_FILE
+                ))
+            })
+    }
+
+    pub fn environment(&self, env: Option<String>) -> String {
+        env.or_else(|| self.binding.as_ref().and_then(|b| b.environment.clone()))
+            .unwrap_or_else(|| self.default_environment.clone())
+    }
+
+    /// Splits `[PROJECT] ARG...` positionals into the project and exactly `N`
+    /// remaining arguments, falling back to the bound project when omitted.
+    /// `rusty env set other KEY` in a bound directory is refused rather than
+    /// setting `other=KEY` in the bound project, when `other` is a project.
+    pub fn split_args<const N: usize>(
+        &self,
+        mut args: Vec<String>,
+    ) -> Result<(String, [String; N])> {
+        let project = match args.len() {
+            n if n == N + 1 => Some(args.remove(0)),
+            n if n == N => {
+                if let (Some(binding), Some(first)) = (&self.binding, args.first())
+                    && *first != binding.project
+                    && self.projects.contains(first)
+                {
+                    return Err(AppError::InvalidInput(format!(
+                        "{} is a project, but this directory is bound to {}; give {} arguments \
+                         to use {}, or name {} first to use it as an argument",
+                        first,
+                        binding.project,
+                        N + 1,
+                        first,
+                        binding.project
+                    )));
+                }
+                None
+            }
+            n => {
+                return Err(AppError::InvalidInput(format!(
+                    "Expected {} or {} arguments, got {}",
+                    N,
+                    N + 1,
+                    n
+                )));
+            }
+        };
+        let project = self.project(project)?;
+        let rest = args
+            .try_into()
+            .map_err(|_| AppError::InvalidInput("Invalid arguments".to_string()))?;
+        Ok((project, rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bound(project: &str) -> Scope {
+        let binding = ProjectBinding {
+            project: project.to_string(),
+            environment: Some("staging".to_string()),
+            path: PathBuf::from(BINDING_FILE),
+        };
+        Scope::new(Some(binding), "development".to_string())
+            .with_projects(vec!["web".to_string(), "api".to_string()])
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_an_optional_leading_project() {
+        let scope = bound("web");
+        assert_eq!(
+            scope.split_args(args(&["KEY", "value"])).unwrap(),
+            ("web".to_string(), ["KEY", "value"].map(String::from))
+        );
+        assert_eq!(
+            scope.split_args(args(&["api", "KEY", "value"])).unwrap(),
+            ("api".to_string(), ["KEY", "value"].map(String::from))
+        );
+        // The bound project's own name is just an argument
+        assert_eq!(
+            scope.split_args::<1>(args(&["web"])).unwrap(),
+            ("web".to_string(), ["web".to_string()])
+        );
+        assert!(scope.split_args::<2>(args(&["KEY"])).is_err());
+        assert_eq!(scope.environment(None), "staging");
+
+        let unbound = Scope::new(None, "development".to_string());
+        assert!(unbound.split_args::<2>(args(&["KEY", "value"])).is_err());
+        assert_eq!(unbound.environment(None), "development");
+    }
+
+    #[test]
+    fn refuses_another_project_in_place_of_an_argument() {
+        let err = bound("web")
+            .split_args::<2>(args(&["api", "KEY"]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("api is a project"), "{}", err);
+    }
+
+    #[test]
+    fn discovers_the_nearest_binding() {
+        let root = std::env::temp_dir().join(format!("rusty-{}", uuid::Uuid::new_v4()));
+        let nested = root.join("service").join("src");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join(BINDING_FILE), "project = \"web\"\n").unwrap();
+        fs::write(
+            root.join("service").join(BINDING_FILE),
+            "project = \"api\"\nenvironment = \"staging\"\n",
+        )
+        .unwrap();
+
+        let binding = ProjectBinding::discover(&nested).unwrap().unwrap();
+        assert_eq!(binding.project, "api");
+        assert_eq!(binding.environment.as_deref(), Some("staging"));
+        assert_eq!(binding.path, root.join("service").join(BINDING_FILE));
+
+        let binding = ProjectBinding::locate(&root.join(BINDING_FILE)).unwrap();
+        assert_eq!(binding.project, "web");
+        assert_eq!(binding.environment, None);
+
+        fs::write(root.join(BINDING_FILE), "environment = \"dev\"\n").unwrap();
+        assert!(matches!(
+            ProjectBinding::discover(&root),
+            Err(AppError::ConfigError(_))
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,3 +1,6 @@
+use crate::diff::SecretDisplay;
+use crate::models::{MergeStrategy, VariableType};
+use crate::shell::Shell;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -16,7 +19,14 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Start the API server
-    Serve,
+    Serve {
+        /// Address to bind (overrides server.host)
+        #[arg(long)]
+        host: Option<String>,
+        /// Port to listen on (overrides server.port)
+        #[arg(long)]
+        port: Option<u16>,
+    },
 
     /// Project management
     #[command(subcommand)]
@@ -25,6 +35,107 @@ pub enum Commands {
     /// Environment variable management
     #[command(subcommand)]
     Env(EnvCommands),
+
+    /// Per-project variable schema
+    #[command(subcommand)]
+    Schema(SchemaCommands),
+
+    /// URLs notified when a project changes
+    #[command(subcommand)]
+    Webhook(WebhookCommands),
+
+    /// Configuration inspection
+    #[command(subcommand)]
+    Config(ConfigCommands),
+
+    /// Run a command with an environment's variables injected
+    Run {
+        /// Project name (default: from .rusty.toml)
+        project: Option<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
+        /// Replace variables already set in the calling environment (default)
+        #[arg(long, overrides_with = "no_override")]
+        r#override: bool,
+        /// Keep variables already set in the calling environment
+        #[arg(long, overrides_with = "override")]
+        no_override: bool,
+        /// Replace values of encrypted variables with *** in the command's output
+        #[arg(long)]
+        redact: bool,
+        /// Command and arguments to run
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Show what `apply` would change to match a manifest; exits 2 on drift
+    Plan {
+        /// YAML manifest describing projects, environments, schemas and values
+        #[arg(short, long, default_value = "rusty.yaml")]
+        file: PathBuf,
+    },
+
+    /// Change the store to match a manifest after confirming the plan
+    Apply {
+        /// YAML manifest describing projects, environments, schemas and values
+        #[arg(short, long, default_value = "rusty.yaml")]
+        file: PathBuf,
+        /// Apply without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Keep env files rendered from a server and reload the app on change
+    Agent {
+        /// Agent configuration: outputs to render, cache file and reload
+        #[arg(short, long, default_value = "rusty-agent.yaml")]
+        file: PathBuf,
+        /// Server URL (default: the file's `server`, then server.host and server.port)
+        #[arg(long)]
+        server: Option<String>,
+        /// Render once and exit instead of watching for changes
+        #[arg(long)]
+        once: bool,
+    },
+
+    /// List export and import formats
+    Formats,
+
+    /// Print shell code that loads bound variables on directory change
+    Hook {
+        /// Shell to generate the hook for
+        shell: Shell,
+    },
+
+    /// Print shell code for the current directory (called by the hook)
+    #[command(hide = true)]
+    HookEnv {
+        /// Shell to generate code for
+        shell: Shell,
+    },
+
+    /// Allow the shell hook to load a directory's .rusty.toml
+    Allow {
+        /// Directory or .rusty.toml (default: nearest to the current directory)
+        path: Option<PathBuf>,
+    },
+
+    /// Revoke a directory's .rusty.toml from the shell hook
+    Deny {
+        /// Directory or .rusty.toml (default: nearest to the current directory)
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Show the resolved configuration
+    Show {
+        /// Show where each value came from
+        #[arg(long)]
+        origin: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -55,56 +166,296 @@ pub enum ProjectCommands {
 pub enum EnvCommands {
     /// Set an environment variable
     Set {
-        /// Project name
-        project: String,
-        /// Variable key
-        key: String,
-        /// Variable value
-        value: String,
-        /// Environment (default: development)
-        #[arg(short, long, default_value = "development")]
-        env: String,
+        /// [PROJECT] KEY VALUE; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "KEY", "VALUE"], num_args = 2..=3, required = true)]
+        args: Vec<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
         /// Encrypt the value
         #[arg(short = 'k', long)]
         encrypted: bool,
+        /// Type the value must parse as (default: the variable's current type, then string)
+        #[arg(short, long = "type", value_enum)]
+        r#type: Option<VariableType>,
+    },
+    /// Set and unset several variables at once; nothing changes if any of them fails
+    SetMany {
+        /// [PROJECT] KEY=VALUE...; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "KEY=VALUE"], num_args = 1.., required_unless_present = "unset")]
+        args: Vec<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
+        /// Delete this variable; repeatable
+        #[arg(short, long, value_name = "KEY")]
+        unset: Vec<String>,
+        /// Encrypt the values being set
+        #[arg(short = 'k', long)]
+        encrypted: bool,
     },
     /// Get an environment variable
     Get {
-        /// Project name
-        project: String,
-        /// Variable key
-        key: String,
-        /// Environment (default: development)
-        #[arg(short, long, default_value = "development")]
-        env: String,
+        /// [PROJECT] KEY; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "KEY"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
+        /// Show the stored value without resolving ${...} references
+        #[arg(long)]
+        raw: bool,
     },
     /// List all variables in an environment
     List {
-        /// Project name
-        project: String,
-        /// Environment (default: development)
-        #[arg(short, long, default_value = "development")]
-        env: String,
+        /// Project name (default: from .rusty.toml)
+        project: Option<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
+        /// Show stored values without resolving ${...} references
+        #[arg(long, conflicts_with = "explain")]
+        raw: bool,
+        /// Show which environment (or schema default) each value comes from
+        #[arg(long)]
+        explain: bool,
     },
     /// Delete an environment variable
     Delete {
-        /// Project name
-        project: String,
-        /// Variable key
-        key: String,
-        /// Environment (default: development)
-        #[arg(short, long, default_value = "development")]
-        env: String,
+        /// [PROJECT] KEY; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "KEY"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
     },
     /// Export environment variables
     Export {
-        /// Project name
-        project: String,
-        /// Environment (default: development)
-        #[arg(short, long, default_value = "development")]
-        env: String,
-        /// Output format (dotenv, json, yaml, docker)
-        #[arg(short, long, default_value = "dotenv")]
-        format: String,
-    },
-}
\ No newline at end of file
+        /// Project name (default: from .rusty.toml)
+        project: Option<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
+        /// Output format, see `rusty formats` (default: defaults.export_format)
+        #[arg(short, long)]
+        format: Option<String>,
+        /// Format-specific option, e.g. -o namespace=prod for k8s
+        #[arg(short = 'o', long = "option", value_name = "KEY=VALUE")]
+        options: Vec<String>,
+    },
+    /// Import variables from a file
+    Import {
+        /// [PROJECT] FILE; use - to read from stdin
+        #[arg(value_names = ["PROJECT", "FILE"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
+        /// Input format, see `rusty formats` (default: from the file extension, then dotenv)
+        #[arg(short, long)]
+        format: Option<String>,
+        /// Format-specific option, e.g. -o separator=. for json
+        #[arg(short = 'o', long = "option", value_name = "KEY=VALUE")]
+        options: Vec<String>,
+        /// How to treat keys that already exist
+        #[arg(short, long, value_enum, default_value_t = MergeStrategy::Overwrite)]
+        strategy: MergeStrategy,
+        /// Mark imported variables as encrypted
+        #[arg(short = 'k', long)]
+        encrypted: bool,
+        /// Show what would change without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Create an empty environment
+    Create {
+        /// [PROJECT] ENV; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "ENV"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+        /// Environment description
+        #[arg(short, long)]
+        description: Option<String>,
+        /// Environment to inherit unset variables from
+        #[arg(short, long)]
+        parent: Option<String>,
+        /// Refuse to rename or remove the environment
+        #[arg(long)]
+        protected: bool,
+    },
+    /// Rename an environment
+    Rename {
+        /// [PROJECT] ENV NEW_NAME; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "ENV", "NEW_NAME"], num_args = 2..=3, required = true)]
+        args: Vec<String>,
+    },
+    /// Copy an environment's variables and settings into a new environment
+    Clone {
+        /// [PROJECT] FROM TO; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "FROM", "TO"], num_args = 2..=3, required = true)]
+        args: Vec<String>,
+    },
+    /// Change an environment's description or protection
+    Update {
+        /// [PROJECT] ENV; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "ENV"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+        /// Environment description
+        #[arg(short, long)]
+        description: Option<String>,
+        /// Whether the environment may be renamed or removed
+        #[arg(long)]
+        protected: Option<bool>,
+    },
+    /// Remove an environment and all of its variables
+    RemoveEnv {
+        /// [PROJECT] ENV; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "ENV"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+    },
+    /// Show keys that differ between two environments or exported files
+    Diff {
+        /// PROJECT/ENV, ENV of the bound project, or a file exported with `env export`
+        /// (file:PATH, ./PATH or NAME.EXT)
+        left: String,
+        /// PROJECT/ENV, ENV of the bound project, or a file exported with `env export`
+        /// (file:PATH, ./PATH or NAME.EXT)
+        right: String,
+        /// How to show encrypted values
+        #[arg(short, long, value_enum, default_value_t = SecretDisplay::Mask)]
+        secrets: SecretDisplay,
+        /// Exit with 1 if there are differences
+        #[arg(long)]
+        exit_code: bool,
+    },
+    /// Copy variables from one environment into another after confirming the plan
+    Promote {
+        /// Project name (default: from .rusty.toml)
+        project: Option<String>,
+        /// Environment to copy from
+        #[arg(long)]
+        from: String,
+        /// Environment to copy into
+        #[arg(long)]
+        to: String,
+        /// Only promote keys matching this glob, e.g. DB_*; repeatable
+        #[arg(short, long, value_name = "GLOB")]
+        include: Vec<String>,
+        /// Skip keys matching this glob; repeatable
+        #[arg(short = 'x', long, value_name = "GLOB")]
+        exclude: Vec<String>,
+        /// Also remove keys the source environment doesn't set
+        #[arg(long)]
+        prune: bool,
+        /// Apply without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Show the plan without applying it
+        #[arg(long, conflicts_with = "yes")]
+        dry_run: bool,
+    },
+    /// Make an environment inherit the variables it doesn't set from another
+    Inherit {
+        /// Project name (default: from .rusty.toml)
+        project: Option<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
+        /// Parent environment
+        #[arg(long, required_unless_present = "none")]
+        from: Option<String>,
+        /// Stop inheriting
+        #[arg(long, conflicts_with = "from")]
+        none: bool,
+    },
+    /// Check an environment against the project schema; exits 1 on violations
+    Validate {
+        /// Project name (default: from .rusty.toml)
+        project: Option<String>,
+        /// Environment (default: .rusty.toml, then defaults.environment)
+        #[arg(short, long)]
+        env: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SchemaCommands {
+    /// Add or replace the rule for a key
+    Set {
+        /// [PROJECT] KEY; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "KEY"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+        /// Type the value must parse as
+        #[arg(short, long = "type", value_enum, default_value_t = VariableType::String)]
+        r#type: VariableType,
+        /// Regular expression the whole value must match
+        #[arg(short, long)]
+        pattern: Option<String>,
+        /// Comma-separated list of the only accepted values
+        #[arg(short, long, value_delimiter = ',')]
+        allowed: Vec<String>,
+        /// Comma-separated environments the key must be set in (* for all)
+        #[arg(short, long, value_delimiter = ',')]
+        required_in: Vec<String>,
+        /// Value used when the key isn't set
+        #[arg(short, long)]
+        default: Option<String>,
+    },
+    /// Remove the rule for a key
+    Remove {
+        /// [PROJECT] KEY; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "KEY"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+    },
+    /// Show a project's schema
+    Show {
+        /// Project name (default: from .rusty.toml)
+        project: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WebhookCommands {
+    /// Register a URL to receive signed change events
+    Add {
+        /// [PROJECT] URL; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "URL"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+        /// Comma-separated event kinds to send, e.g. variables.changed (default: all)
+        #[arg(long = "event", value_delimiter = ',')]
+        events: Vec<String>,
+        /// Comma-separated environments to send events for (default: all)
+        #[arg(short, long = "env", value_delimiter = ',')]
+        environments: Vec<String>,
+        /// Signing secret (default: randomly generated)
+        #[arg(short, long)]
+        secret: Option<String>,
+    },
+    /// List a project's webhooks
+    List {
+        /// Project name (default: from .rusty.toml)
+        project: Option<String>,
+    },
+    /// Remove a webhook
+    Remove {
+        /// [PROJECT] ID; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "ID"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+    },
+    /// Send a ping event to a webhook and report the response
+    Test {
+        /// [PROJECT] ID; the project may be omitted inside a directory with a .rusty.toml
+        #[arg(value_names = ["PROJECT", "ID"], num_args = 1..=2, required = true)]
+        args: Vec<String>,
+    },
+    /// Show recent deliveries, newest first
+    Deliveries {
+        /// Project name (default: from .rusty.toml)
+        project: Option<String>,
+        /// Only show deliveries to this webhook
+        #[arg(short, long)]
+        webhook: Option<String>,
+        /// Number of deliveries to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+}
@@ -1,8 +1,26 @@
 use crate::error::{AppError, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::PathBuf;
 
-#[derive(Debug, Deserialize, Clone)]
+/// Prefix for environment variable overrides, e.g. `RUSTY_SERVER__PORT=9000`.
+const ENV_PREFIX: &str = "RUSTY";
+const ENV_SEPARATOR: &str = "__";
+
+/// Every configurable key, in display order.
+pub const CONFIG_KEYS: &[&str] = &[
+    "server.host",
+    "server.port",
+    "database.path",
+    "database.auto_backup",
+    "database.backup_dir",
+    "defaults.environment",
+    "defaults.export_format",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
@@ -17,7 +35,8 @@ impl Default for ServerConfig {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct DatabaseConfig {
     pub path: PathBuf,
     #[allow(dead_code)]
@@ -36,11 +55,10 @@ impl Default for DatabaseConfig {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct DefaultsConfig {
-    #[allow(dead_code)]
     pub environment: String,
-    #[allow(dead_code)]
     pub export_format: String,
 }
 
@@ -53,33 +71,213 @@ impl Default for DefaultsConfig {
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Default)]
+/// Where a configuration value was taken from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigOrigin {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Cli(String),
+}
+
+impl fmt::Display for ConfigOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigOrigin::Default => write!(f, "default"),
+            ConfigOrigin::File(path) => write!(f, "file {}", path.display()),
+            ConfigOrigin::Env(var) => write!(f, "env {}", var),
+            ConfigOrigin::Cli(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+/// A value supplied on the command line, applied on top of every other layer.
+pub struct CliOverride {
+    pub key: &'static str,
+    pub flag: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct AppConfig {
     #[serde(default)]
     pub server: ServerConfig,
     #[serde(default)]
     pub database: DatabaseConfig,
     #[serde(default)]
-    #[allow(dead_code)]
     pub defaults: DefaultsConfig,
+    #[serde(skip)]
+    origins: BTreeMap<String, ConfigOrigin>,
 }
 
 impl AppConfig {
-    pub fn load(config_path: Option<PathBuf>) -> Result<Self> {
-        let config_file = config_path.unwrap_or_else(|| PathBuf::from("config.yaml"));
+    /// Resolves configuration from, lowest to highest precedence: built-in
+    /// defaults, `/etc/rusty/config.yaml`, `$XDG_CONFIG_HOME/rusty/config.yaml`,
+    /// `./config.yaml` (or the `--config` path), `RUSTY_*` environment
+    /// variables and command-line flags.
+    pub fn load(config_path: Option<PathBuf>, overrides: Vec<CliOverride>) -> Result<Self> {
+        let mut builder = config::Config::builder();
+        let mut origins: BTreeMap<String, ConfigOrigin> = CONFIG_KEYS
+            .iter()
+            .map(|key| (key.to_string(), ConfigOrigin::Default))
+            .collect();
+
+        for path in Self::config_files(config_path) {
+            if !path.exists() {
+                continue;
+            }
+
+            let layer = config::Config::builder()
+                .add_source(config::File::from(path.clone()))
+                .build()
+                .map_err(|e| AppError::ConfigError(e.to_string()))?;
+            for key in CONFIG_KEYS {
+                if layer.get::<config::Value>(key).is_ok() {
+                    origins.insert(key.to_string(), ConfigOrigin::File(path.clone()));
+                }
+            }
+
+            builder = builder.add_source(config::File::from(path));
+        }
+
+        for key in CONFIG_KEYS {
+            let var = Self::env_var_name(key);
+            if std::env::var_os(&var).is_some_and(|value| !value.is_empty()) {
+                origins.insert(key.to_string(), ConfigOrigin::Env(var));
+            }
+        }
+        builder = builder.add_source(
+            config::Environment::with_prefix(ENV_PREFIX)
+                .prefix_separator("_")
+                .separator(ENV_SEPARATOR)
+                .ignore_empty(true),
+        );
 
-        if !config_file.exists() {
-            // Return default config if file doesn't exist
-            return Ok(Self::default());
+        for CliOverride { key, flag, value } in overrides {
+            builder = builder
+                .set_override(key, value)
+                .map_err(|e| AppError::ConfigError(e.to_string()))?;
+            origins.insert(key.to_string(), ConfigOrigin::Cli(flag.to_string()));
         }
 
-        let settings = config::Config::builder()
-            .add_source(config::File::from(config_file))
+        let settings = builder
             .build()
             .map_err(|e| AppError::ConfigError(e.to_string()))?;
 
-        settings
+        let mut config: AppConfig = settings
             .try_deserialize()
-            .map_err(|e| AppError::ConfigError(e.to_string()))
+            .map_err(|e| AppError::ConfigError(e.to_string()))?;
+        config.origins = origins;
+        Ok(config)
     }
-}
\ No newline at end of file
+
+    /// Candidate configuration files in increasing order of precedence.
+    fn config_files(config_path: Option<PathBuf>) -> Vec<PathBuf> {
+        let mut files = vec![PathBuf::from("/etc/rusty/config.yaml")];
+
+        let xdg_config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+        if let Some(dir) = xdg_config_home {
+            files.push(dir.join("rusty").join("config.yaml"));
+        }
+
+        files.push(config_path.unwrap_or_else(|| PathBuf::from("config.yaml")));
+        files
+    }
+
+    /// Environment variable that overrides `key`, e.g. `RUSTY_DATABASE__PATH`.
+    pub fn env_var_name(key: &str) -> String {
+        format!(
+            "{}_{}",
+            ENV_PREFIX,
+            key.replace('.', ENV_SEPARATOR).to_uppercase()
+        )
+    }
+
+    /// Resolved values paired with the layer each one came from.
+    pub fn entries(&self) -> Result<Vec<(&'static str, String, ConfigOrigin)>> {
+        let tree = serde_json::to_value(self)?;
+
+        Ok(CONFIG_KEYS
+            .iter()
+            .map(|key| {
+                let value = key
+                    .split('.')
+                    .try_fold(&tree, |node, part| node.get(part))
+                    .map(|value| match value {
+                        serde_json::Value::String(s) => s.clone(),
+                        serde_json::Value::Null => String::new(),
+                        other => other.to_string(),
+                    })
+                    .unwrap_or_default();
+                let origin = self
+                    .origins
+                    .get(*key)
+                    .cloned()
+                    .unwrap_or(ConfigOrigin::Default);
+                (*key, value, origin)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin(config: &AppConfig, key: &str) -> ConfigOrigin {
+        config.origins[key].clone()
+    }
+
+    #[test]
+    fn layers_flags_over_files_over_defaults() {
+        let path = std::env::temp_dir().join(format!("rusty-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "server:\n  port: 9000\ndefaults:\n  environment: staging\n",
+        )
+        .unwrap();
+        let config = AppConfig::load(
+            Some(path.clone()),
+            vec![CliOverride {
+                key: "server.port",
+                flag: "--port",
+                value: "9100".to_string(),
+            }],
+        )
+        .unwrap();
+
+        assert_eq!(config.server.port, 9100);
+        assert_eq!(
+            origin(&config, "server.port"),
+            ConfigOrigin::Cli("--port".to_string())
+        );
+        assert_eq!(config.defaults.environment, "staging");
+        assert_eq!(
+            origin(&config, "defaults.environment"),
+            ConfigOrigin::File(path.clone())
+        );
+        assert_eq!(config.defaults.export_format, "dotenv");
+
+        std::fs::write(&path, "server:\n  port: not-a-port\n").unwrap();
+        assert!(matches!(
+            AppConfig::load(Some(path.clone()), Vec::new()),
+            Err(AppError::ConfigError(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn names_environment_overrides() {
+        assert_eq!(
+            AppConfig::env_var_name("database.path"),
+            "RUSTY_DATABASE__PATH"
+        );
+        assert_eq!(
+            AppConfig::env_var_name("defaults.export_format"),
+            "RUSTY_DEFAULTS__EXPORT_FORMAT"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+mod resolve;
 mod store;
 
-pub use store::JsonStore;
\ No newline at end of file
+pub use store::{JsonStore, Precondition, Target};
@@ -1,15 +1,58 @@
+use super::resolve::{self, Source};
+use crate::diff::{self, Change, DiffEntry, SecretDisplay};
 use crate::error::{AppError, Result};
-use crate::models::{Database, EnvVariable, Environment, Project};
-use std::collections::HashMap;
+use crate::models::{
+    ChangeEvent, ChangeKind, Database, Delivery, EnvVariable, Environment, EnvironmentPatch,
+    EnvironmentSettings, ImportOptions, ImportReport, MergeStrategy, Project, PromoteOptions,
+    Schema, SchemaRule, VariableType, Violation, Webhook, schema,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+use tokio::time::Instant;
 
 #[derive(Clone)]
 pub struct JsonStore {
     db: Arc<RwLock<Database>>,
     file_path: PathBuf,
+    precondition: Option<Precondition>,
+    events: broadcast::Sender<ChangeEvent>,
+}
+
+/// Webhook deliveries kept per project.
+pub const MAX_DELIVERIES: usize = 100;
+
+/// Changes a watcher can fall behind by before it misses some.
+const EVENT_BUFFER: usize = 256;
+
+/// Something that has a revision, for `If-Match` checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Project(String),
+    Environment(String, String),
+    Variable(String, String, String),
+}
+
+/// Writes go ahead only while `target`'s revision is one of `revisions`,
+/// or while it exists at all when `revisions` is `None` (`If-Match: *`).
+#[derive(Debug, Clone)]
+pub struct Precondition {
+    pub target: Target,
+    pub revisions: Option<Vec<u64>>,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Project(project) => write!(f, "{}", project),
+            Self::Environment(project, env) => write!(f, "{}/{}", project, env),
+            Self::Variable(project, env, key) => write!(f, "{}/{}:{}", project, env, key),
+        }
+    }
 }
 
 impl JsonStore {
@@ -29,9 +72,122 @@ impl JsonStore {
         Ok(Self {
             db: Arc::new(RwLock::new(db)),
             file_path,
+            precondition: None,
+            events: broadcast::channel(EVENT_BUFFER).0,
         })
     }
 
+    /// Re-reads the database file, picking up changes other processes have
+    /// saved since the store was opened.
+    pub async fn reload(&self) -> Result<()> {
+        if self.file_path.exists() {
+            let contents = fs::read_to_string(&self.file_path)?;
+            *self.db.write().await = serde_json::from_str(&contents)?;
+        }
+        Ok(())
+    }
+
+    /// Changes saved from now on, in revision order.
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.events.subscribe()
+    }
+
+    fn notify(
+        &self,
+        revision: u64,
+        kind: ChangeKind,
+        project: &str,
+        environment: Option<&str>,
+        keys: Vec<String>,
+    ) {
+        // Nobody listening isn't an error
+        let _ = self.events.send(ChangeEvent {
+            revision,
+            kind,
+            project: project.to_string(),
+            environment: environment.map(str::to_string),
+            keys,
+            webhooks: Vec::new(),
+        });
+    }
+
+    /// A handle to the same store whose writes fail with
+    /// [`AppError::PreconditionFailed`] unless `precondition` holds. It's
+    /// checked under the write lock, so a concurrent change can't slip in
+    /// between the check and the write.
+    pub fn with_precondition(&self, precondition: Precondition) -> Self {
+        Self {
+            precondition: Some(precondition),
+            ..self.clone()
+        }
+    }
+
+    /// An index that grows whenever the resolved view of `env` might have
+    /// changed, see [`resolve::watch_index`].
+    pub async fn watch_index(&self, project_name: &str, env: &str) -> Result<u64> {
+        let db = self.db.read().await;
+        let project = db
+            .projects
+            .get(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+        if !project.environments.contains_key(env) {
+            return Err(AppError::EnvironmentNotFound(env.to_string()));
+        }
+
+        resolve::watch_index(&db, project_name, env)
+    }
+
+    /// Waits until the [`watch_index`](Self::watch_index) of `env` is
+    /// greater than `index`, or `timeout` passes, and returns it.
+    pub async fn wait_for_change(
+        &self,
+        project_name: &str,
+        env: &str,
+        index: u64,
+        timeout: Duration,
+    ) -> Result<u64> {
+        let deadline = Instant::now() + timeout;
+        // Subscribe before checking so a change in between isn't missed
+        let mut events = self.subscribe();
+        loop {
+            let current = self.watch_index(project_name, env).await?;
+            if current > index {
+                return Ok(current);
+            }
+            match tokio::time::timeout_at(deadline, events.recv()).await {
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => {}
+                Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => return Ok(current),
+            }
+        }
+    }
+
+    /// The current revision of `target`.
+    pub async fn revision(&self, target: &Target) -> Result<u64> {
+        let db = self.db.read().await;
+        revision_of(&db, target)
+    }
+
+    fn check_precondition(&self, db: &Database) -> Result<()> {
+        let Some(precondition) = &self.precondition else {
+            return Ok(());
+        };
+        // A target that doesn't exist never matches, not even `*`
+        let matches = revision_of(db, &precondition.target).is_ok_and(|current| {
+            precondition
+                .revisions
+                .as_ref()
+                .is_none_or(|revisions| revisions.contains(&current))
+        });
+        if matches {
+            Ok(())
+        } else {
+            Err(AppError::PreconditionFailed(format!(
+                "{} has changed since it was read",
+                precondition.target
+            )))
+        }
+    }
+
     async fn save(&self) -> Result<()> {
         let db = self.db.read().await;
         let json = serde_json::to_string_pretty(&*db)?;
@@ -46,16 +202,27 @@ impl JsonStore {
         description: Option<String>,
     ) -> Result<Project> {
         let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
 
         if db.projects.contains_key(&name) {
             return Err(AppError::ProjectAlreadyExists(name));
         }
 
-        let project = Project::new(name.clone(), description);
+        let mut project = Project::new(name.clone(), description);
+        project.revision = revision;
         db.projects.insert(name, project.clone());
+        db.metadata.revision = revision;
         drop(db);
 
         self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::ProjectCreated,
+            &project.name,
+            None,
+            Vec::new(),
+        );
         Ok(project)
     }
 
@@ -79,12 +246,15 @@ impl JsonStore {
         description: Option<String>,
     ) -> Result<Project> {
         let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
 
         // Check if new_name conflicts before getting mutable reference
-        if let Some(ref new_name) = new_name {
-            if new_name != name && db.projects.contains_key(new_name) {
-                return Err(AppError::ProjectAlreadyExists(new_name.clone()));
-            }
+        if let Some(ref new_name) = new_name
+            && new_name != name
+            && db.projects.contains_key(new_name)
+        {
+            return Err(AppError::ProjectAlreadyExists(new_name.clone()));
         }
 
         let project = db
@@ -99,35 +269,61 @@ impl JsonStore {
         if let Some(new_name) = new_name {
             let mut updated_project = project.clone();
             updated_project.name = new_name.clone();
-            updated_project.update_timestamp();
+            updated_project.touch(revision);
 
             db.projects.remove(name);
-            db.projects.insert(new_name, updated_project.clone());
+            db.projects
+                .insert(new_name.clone(), updated_project.clone());
+            if let Some(webhooks) = db.webhooks.remove(name) {
+                db.webhooks.insert(new_name, webhooks);
+            }
+            db.metadata.revision = revision;
             drop(db);
 
             self.save().await?;
+            self.notify(
+                revision,
+                ChangeKind::ProjectUpdated,
+                &updated_project.name,
+                None,
+                Vec::new(),
+            );
             return Ok(updated_project);
         }
 
-        project.update_timestamp();
+        project.touch(revision);
         let updated_project = project.clone();
+        db.metadata.revision = revision;
         drop(db);
 
         self.save().await?;
+        self.notify(revision, ChangeKind::ProjectUpdated, name, None, Vec::new());
         Ok(updated_project)
     }
 
     pub async fn delete_project(&self, name: &str) -> Result<()> {
         let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
 
         if !db.projects.contains_key(name) {
             return Err(AppError::ProjectNotFound(name.to_string()));
         }
 
         db.projects.remove(name);
+        let webhooks = db.webhooks.remove(name).unwrap_or_default().webhooks;
+        db.metadata.revision = revision;
         drop(db);
 
         self.save().await?;
+        let _ = self.events.send(ChangeEvent {
+            revision,
+            kind: ChangeKind::ProjectDeleted,
+            project: name.to_string(),
+            environment: None,
+            keys: Vec::new(),
+            webhooks,
+        });
         Ok(())
     }
 
@@ -139,28 +335,46 @@ impl JsonStore {
         key: String,
         value: String,
         encrypted: bool,
+        value_type: Option<VariableType>,
     ) -> Result<EnvVariable> {
         let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
 
         let project = db
             .projects
             .get_mut(project_name)
             .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
 
+        let value_type = variable_type(project, env, &key, value_type)?;
+        validate_value(&key, &value, value_type)?;
+        check_rule(&project.schema, &key, &value)?;
+
         let environment = project
             .environments
             .entry(env.to_string())
             .or_insert_with(HashMap::new);
 
-        let variable = EnvVariable::new(value, encrypted);
-        environment.insert(key, variable.clone());
-        project.update_timestamp();
+        let mut variable = EnvVariable::new(value, encrypted);
+        variable.value_type = value_type;
+        variable.revision = revision;
+        environment.insert(key.clone(), variable.clone());
+        project.touch_environment(env, revision);
+        db.metadata.revision = revision;
 
         drop(db);
         self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::VariablesChanged,
+            project_name,
+            Some(env),
+            vec![key],
+        );
         Ok(variable)
     }
 
+    /// A variable with references resolved, see [`resolve::resolve_environment`].
     pub async fn get_variable(
         &self,
         project_name: &str,
@@ -168,25 +382,18 @@ impl JsonStore {
         key: &str,
     ) -> Result<EnvVariable> {
         let db = self.db.read().await;
-
-        let project = db
-            .projects
-            .get(project_name)
-            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
-
-        let environment = project
-            .environments
-            .get(env)
-            .ok_or_else(|| AppError::EnvironmentNotFound(env.to_string()))?;
-
-        environment
-            .get(key)
-            .cloned()
-            .ok_or_else(|| AppError::VariableNotFound(key.to_string()))
+        resolve::resolve_variable(&db, project_name, env, key)
     }
 
+    /// An environment with schema defaults applied and references resolved.
     pub async fn get_environment(&self, project_name: &str, env: &str) -> Result<Environment> {
         let db = self.db.read().await;
+        resolve::resolve_environment(&db, project_name, env)
+    }
+
+    /// An environment exactly as stored, with references left unexpanded.
+    pub async fn get_raw_environment(&self, project_name: &str, env: &str) -> Result<Environment> {
+        let db = self.db.read().await;
 
         let project = db
             .projects
@@ -214,28 +421,1241 @@ impl JsonStore {
         Ok(project.environments.clone())
     }
 
+    /// Creates an empty environment, or a copy of `clone_from`'s variables
+    /// and settings. Explicit `settings` fields win over the copied ones.
+    pub async fn create_environment(
+        &self,
+        project_name: &str,
+        env: &str,
+        settings: EnvironmentSettings,
+        clone_from: Option<&str>,
+    ) -> Result<EnvironmentSettings> {
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+        let project = db
+            .projects
+            .get_mut(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        if project.environments.contains_key(env) {
+            return Err(AppError::EnvironmentAlreadyExists(env.to_string()));
+        }
+        if let Some(parent) = &settings.parent
+            && !project.environments.contains_key(parent)
+        {
+            return Err(AppError::EnvironmentNotFound(parent.clone()));
+        }
+
+        let (vars, settings) = match clone_from {
+            Some(source) => {
+                let vars = project
+                    .environments
+                    .get(source)
+                    .cloned()
+                    .ok_or_else(|| AppError::EnvironmentNotFound(source.to_string()))?;
+                let copied = project
+                    .environment_settings
+                    .get(source)
+                    .cloned()
+                    .unwrap_or_default();
+                let settings = EnvironmentSettings {
+                    parent: settings.parent.or(copied.parent),
+                    description: settings.description.or(copied.description),
+                    protected: settings.protected,
+                    ..Default::default()
+                };
+                (vars, settings)
+            }
+            None => (Environment::new(), settings),
+        };
+
+        let settings = EnvironmentSettings {
+            revision,
+            ..settings
+        };
+        project.environments.insert(env.to_string(), vars);
+        project
+            .environment_settings
+            .insert(env.to_string(), settings.clone());
+        project.touch(revision);
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::EnvironmentCreated,
+            project_name,
+            Some(env),
+            Vec::new(),
+        );
+        Ok(settings)
+    }
+
+    /// Updates an environment's settings, renaming it when `new_name` is
+    /// given. Environments inheriting from it, webhook filters and schema
+    /// `required_in` lists follow the rename; values that refer to it by
+    /// name make the rename fail instead.
+    pub async fn update_environment(
+        &self,
+        project_name: &str,
+        env: &str,
+        new_name: Option<String>,
+        description: Option<String>,
+        protected: Option<bool>,
+    ) -> Result<EnvironmentSettings> {
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+        let project = db
+            .projects
+            .get(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        if !project.environments.contains_key(env) {
+            return Err(AppError::EnvironmentNotFound(env.to_string()));
+        }
+        let mut settings = project
+            .environment_settings
+            .get(env)
+            .cloned()
+            .unwrap_or_default();
+
+        // Checked against the stored settings, so a single update can't
+        // unprotect an environment and rename it too
+        let new_name = new_name.filter(|new_name| new_name != env);
+        if let Some(new_name) = &new_name {
+            if settings.protected {
+                return Err(AppError::EnvironmentProtected(env.to_string()));
+            }
+            if project.environments.contains_key(new_name) {
+                return Err(AppError::EnvironmentAlreadyExists(new_name.clone()));
+            }
+            // Values naming the environment would dangle after the rename
+            let referrers = resolve::references_to(&db, project_name, env);
+            if !referrers.is_empty() {
+                return Err(AppError::InvalidInput(format!(
+                    "cannot rename {}: referred to by {}",
+                    env,
+                    referrers.join(", ")
+                )));
+            }
+        }
+
+        let project = db
+            .projects
+            .get_mut(project_name)
+            .expect("the project was found above");
+        let name = match new_name {
+            Some(new_name) => {
+                let vars = project.environments.remove(env).unwrap_or_default();
+                project.environments.insert(new_name.clone(), vars);
+                project.environment_settings.remove(env);
+                for child in project.environment_settings.values_mut() {
+                    if child.parent.as_deref() == Some(env) {
+                        child.parent = Some(new_name.clone());
+                    }
+                }
+                let mut schema_changed = false;
+                for rule in project.schema.values_mut() {
+                    for required in &mut rule.required_in {
+                        if required == env {
+                            *required = new_name.clone();
+                            schema_changed = true;
+                        }
+                    }
+                }
+                if schema_changed {
+                    project.touch_schema(revision);
+                }
+                new_name
+            }
+            None => env.to_string(),
+        };
+        if let Some(description) = description {
+            settings.description = Some(description);
+        }
+        if let Some(protected) = protected {
+            settings.protected = protected;
+        }
+
+        settings.revision = revision;
+        project
+            .environment_settings
+            .insert(name.clone(), settings.clone());
+        project.touch(revision);
+        if name != env
+            && let Some(hooks) = db.webhooks.get_mut(project_name)
+        {
+            for webhook in &mut hooks.webhooks {
+                for filter in &mut webhook.environments {
+                    if filter == env {
+                        *filter = name.clone();
+                    }
+                }
+            }
+        }
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::EnvironmentUpdated,
+            project_name,
+            Some(&name),
+            Vec::new(),
+        );
+        Ok(settings)
+    }
+
+    /// Removes an environment and its variables. Protected environments and
+    /// environments others inherit from are refused.
+    pub async fn remove_environment(&self, project_name: &str, env: &str) -> Result<()> {
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+        let project = db
+            .projects
+            .get_mut(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        if !project.environments.contains_key(env) {
+            return Err(AppError::EnvironmentNotFound(env.to_string()));
+        }
+        if project
+            .environment_settings
+            .get(env)
+            .is_some_and(|settings| settings.protected)
+        {
+            return Err(AppError::EnvironmentProtected(env.to_string()));
+        }
+        let mut children: Vec<&str> = project
+            .environment_settings
+            .iter()
+            .filter(|(_, settings)| settings.parent.as_deref() == Some(env))
+            .map(|(child, _)| child.as_str())
+            .collect();
+        if !children.is_empty() {
+            children.sort();
+            return Err(AppError::EnvironmentInUse(format!(
+                "{} is inherited by {}",
+                env,
+                children.join(", ")
+            )));
+        }
+
+        project.environments.remove(env);
+        project.environment_settings.remove(env);
+        project.touch(revision);
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::EnvironmentRemoved,
+            project_name,
+            Some(env),
+            Vec::new(),
+        );
+        Ok(())
+    }
+
     pub async fn delete_variable(&self, project_name: &str, env: &str, key: &str) -> Result<()> {
         let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
 
         let project = db
             .projects
             .get_mut(project_name)
             .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
 
-        let environment = project
+        if !project
             .environments
-            .get_mut(env)
-            .ok_or_else(|| AppError::EnvironmentNotFound(env.to_string()))?;
+            .get(env)
+            .ok_or_else(|| AppError::EnvironmentNotFound(env.to_string()))?
+            .contains_key(key)
+        {
+            return Err(AppError::VariableNotFound(key.to_string()));
+        }
+        check_removal(project, env, key)?;
 
-        if !environment.contains_key(key) {
+        if let Some(environment) = project.environments.get_mut(env) {
+            environment.remove(key);
+        }
+        project.touch_environment(env, revision);
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::VariablesChanged,
+            project_name,
+            Some(env),
+            vec![key.to_string()],
+        );
+        Ok(())
+    }
+
+    /// Applies a batch of sets and deletes with a single save. If any key
+    /// fails validation nothing is written. Deleting a key that isn't set
+    /// is a no-op, as in JSON Merge Patch.
+    pub async fn patch_environment(
+        &self,
+        project_name: &str,
+        env: &str,
+        patch: EnvironmentPatch,
+    ) -> Result<Environment> {
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+
+        let project = db
+            .projects
+            .get_mut(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        let mut environment = project.environments.get(env).cloned().unwrap_or_default();
+        let keys: Vec<String> = patch.keys().cloned().collect();
+        let now = chrono::Utc::now();
+
+        for (key, change) in patch {
+            let Some(change) = change else {
+                if environment.contains_key(&key) {
+                    check_removal(project, env, &key)?;
+                    environment.remove(&key);
+                }
+                continue;
+            };
+
+            let fields = change.into_fields();
+            let existing = environment.get(&key);
+            let value = match (fields.value, existing) {
+                (Some(value), _) => value,
+                (None, Some(existing)) => existing.value.clone(),
+                (None, None) => {
+                    return Err(AppError::InvalidInput(format!(
+                        "{}: a value is required for a new variable",
+                        key
+                    )));
+                }
+            };
+            let value_type = variable_type(project, env, &key, fields.value_type)?;
+            validate_value(&key, &value, value_type)?;
+            check_rule(&project.schema, &key, &value)?;
+
+            let mut variable = existing
+                .cloned()
+                .unwrap_or_else(|| EnvVariable::new(String::new(), false));
+            variable.value = value;
+            variable.value_type = value_type;
+            if let Some(encrypted) = fields.encrypted {
+                variable.encrypted = encrypted;
+            }
+            variable.updated_at = now;
+            variable.revision = revision;
+            environment.insert(key, variable);
+        }
+
+        project
+            .environments
+            .insert(env.to_string(), environment.clone());
+        project.touch_environment(env, revision);
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::VariablesChanged,
+            project_name,
+            Some(env),
+            keys,
+        );
+        Ok(environment)
+    }
+
+    /// Merges `vars` into an environment with a single save. With
+    /// `dry_run` the report is computed but nothing is written.
+    pub async fn import_variables(
+        &self,
+        project_name: &str,
+        env: &str,
+        vars: Vec<(String, String)>,
+        options: ImportOptions,
+    ) -> Result<ImportReport> {
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+
+        let project = db
+            .projects
+            .get_mut(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        // Later duplicates win, as they would when sourcing the file
+        let vars: BTreeMap<String, String> = vars.into_iter().collect();
+        let mut environment = project.environments.get(env).cloned().unwrap_or_default();
+        let mut report = ImportReport {
+            dry_run: options.dry_run,
+            ..Default::default()
+        };
+
+        for (key, value) in vars {
+            check_references(&key, &value)?;
+            check_rule(&project.schema, &key, &value)?;
+            match environment.get_mut(&key) {
+                None => {
+                    // Typed as `set_variable` would, including an inherited type
+                    let value_type = variable_type(project, env, &key, None)?;
+                    validate_value(&key, &value, value_type)?;
+                    let mut variable = EnvVariable::new(value, options.encrypted);
+                    variable.value_type = value_type;
+                    variable.revision = revision;
+                    environment.insert(key.clone(), variable);
+                    report.added.push(key);
+                }
+                Some(existing) if existing.value == value => report.unchanged.push(key),
+                Some(_) if options.strategy == MergeStrategy::SkipExisting => {
+                    report.skipped.push(key)
+                }
+                Some(existing) => {
+                    validate_value(&key, &value, existing.value_type)?;
+                    existing.value = value;
+                    existing.encrypted |= options.encrypted;
+                    existing.updated_at = chrono::Utc::now();
+                    existing.revision = revision;
+                    report.changed.push(key);
+                }
+            }
+        }
+
+        if options.strategy == MergeStrategy::FailOnConflict && !report.changed.is_empty() {
+            return Err(AppError::VariableConflict(report.changed.join(", ")));
+        }
+
+        if options.dry_run {
+            return Ok(report);
+        }
+
+        project.environments.insert(env.to_string(), environment);
+        project.touch_environment(env, revision);
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::VariablesChanged,
+            project_name,
+            Some(env),
+            [report.added.clone(), report.changed.clone()].concat(),
+        );
+        Ok(report)
+    }
+
+    /// Copies the variables `from` sets itself into `to`, with a single save.
+    /// Values are copied as stored, so references resolve in the target.
+    /// Returns the plan, with encrypted values masked; with `dry_run`
+    /// nothing is written.
+    pub async fn promote(
+        &self,
+        project_name: &str,
+        from: &str,
+        to: &str,
+        options: &PromoteOptions,
+    ) -> Result<Vec<DiffEntry>> {
+        if from == to {
+            return Err(AppError::InvalidInput(format!(
+                "cannot promote {} into itself",
+                from
+            )));
+        }
+
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+
+        let project = db
+            .projects
+            .get_mut(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        let source = project
+            .environments
+            .get(from)
+            .ok_or_else(|| AppError::EnvironmentNotFound(from.to_string()))?;
+        let mut target = project.environments.get(to).cloned().unwrap_or_default();
+
+        let plan: Vec<DiffEntry> = diff::diff(&target, source, SecretDisplay::Mask)
+            .into_iter()
+            .filter(|entry| diff::selected(&entry.key, &options.include, &options.exclude))
+            .filter(|entry| entry.change != Change::Removed || options.prune)
+            .collect();
+
+        for entry in &plan {
+            match source.get(&entry.key) {
+                Some(var) => {
+                    validate_value(&entry.key, &var.value, var.value_type)?;
+                    check_rule(&project.schema, &entry.key, &var.value)?;
+                    let mut variable = var.clone();
+                    variable.updated_at = chrono::Utc::now();
+                    variable.revision = revision;
+                    if let Some(existing) = target.get(&entry.key) {
+                        variable.created_at = existing.created_at;
+                    }
+                    target.insert(entry.key.clone(), variable);
+                }
+                None => {
+                    check_removal(project, to, &entry.key)?;
+                    target.remove(&entry.key);
+                }
+            }
+        }
+
+        if options.dry_run || plan.is_empty() {
+            return Ok(plan);
+        }
+
+        project.environments.insert(to.to_string(), target);
+        project.touch_environment(to, revision);
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::VariablesChanged,
+            project_name,
+            Some(to),
+            plan.iter().map(|entry| entry.key.clone()).collect(),
+        );
+        Ok(plan)
+    }
+
+    /// The resolved environment with the layer each key came from.
+    pub async fn explain_environment(
+        &self,
+        project_name: &str,
+        env: &str,
+    ) -> Result<Vec<(String, EnvVariable, Source)>> {
+        let db = self.db.read().await;
+        resolve::explain(&db, project_name, env)
+    }
+
+    /// Makes `env` inherit the variables it doesn't set from `parent`, or
+    /// stops inheriting with `None`. The environment is created if needed.
+    pub async fn set_parent(
+        &self,
+        project_name: &str,
+        env: &str,
+        parent: Option<String>,
+    ) -> Result<()> {
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+        let project = db
+            .projects
+            .get_mut(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        if let Some(parent) = &parent
+            && !project.environments.contains_key(parent)
+        {
+            return Err(AppError::EnvironmentNotFound(parent.clone()));
+        }
+
+        let mut updated = project.clone();
+        updated.environments.entry(env.to_string()).or_default();
+        updated
+            .environment_settings
+            .entry(env.to_string())
+            .or_default()
+            .parent = parent;
+        resolve::lineage(&updated, env)?;
+
+        updated.touch_environment(env, revision);
+        *project = updated;
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::EnvironmentUpdated,
+            project_name,
+            Some(env),
+            Vec::new(),
+        );
+        Ok(())
+    }
+
+    // Schema operations
+    pub async fn set_schema_rule(
+        &self,
+        project_name: &str,
+        key: String,
+        rule: SchemaRule,
+    ) -> Result<SchemaRule> {
+        rule.validate()
+            .map_err(|reason| AppError::InvalidInput(format!("{}: {}", key, reason)))?;
+        if let Some(default) = &rule.default {
+            check_references(&key, default)?;
+        }
+
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+        let project = db
+            .projects
+            .get_mut(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        project.schema.insert(key.clone(), rule.clone());
+        project.touch_schema(revision);
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::SchemaChanged,
+            project_name,
+            None,
+            vec![key],
+        );
+        Ok(rule)
+    }
+
+    pub async fn remove_schema_rule(&self, project_name: &str, key: &str) -> Result<()> {
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+        let project = db
+            .projects
+            .get_mut(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        if project.schema.remove(key).is_none() {
             return Err(AppError::VariableNotFound(key.to_string()));
         }
+        project.touch_schema(revision);
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::SchemaChanged,
+            project_name,
+            None,
+            vec![key.to_string()],
+        );
+        Ok(())
+    }
+
+    // Webhook operations
+    pub async fn list_webhooks(&self, project_name: &str) -> Result<Vec<Webhook>> {
+        let db = self.db.read().await;
+        if !db.projects.contains_key(project_name) {
+            return Err(AppError::ProjectNotFound(project_name.to_string()));
+        }
+        Ok(db
+            .webhooks
+            .get(project_name)
+            .map(|hooks| hooks.webhooks.clone())
+            .unwrap_or_default())
+    }
+
+    pub async fn add_webhook(&self, project_name: &str, webhook: Webhook) -> Result<Webhook> {
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+        db.projects
+            .get_mut(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?
+            .touch(revision);
+
+        db.webhooks
+            .entry(project_name.to_string())
+            .or_default()
+            .webhooks
+            .push(webhook.clone());
+        db.metadata.revision = revision;
+
+        drop(db);
+        self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::ProjectUpdated,
+            project_name,
+            None,
+            Vec::new(),
+        );
+        Ok(webhook)
+    }
+
+    pub async fn remove_webhook(&self, project_name: &str, id: &str) -> Result<()> {
+        let mut db = self.db.write().await;
+        self.check_precondition(&db)?;
+        let revision = db.next_revision();
+        if !db.projects.contains_key(project_name) {
+            return Err(AppError::ProjectNotFound(project_name.to_string()));
+        }
 
-        environment.remove(key);
-        project.update_timestamp();
+        let hooks = db
+            .webhooks
+            .get_mut(project_name)
+            .filter(|hooks| hooks.webhooks.iter().any(|webhook| webhook.id == id))
+            .ok_or_else(|| AppError::WebhookNotFound(id.to_string()))?;
+        hooks.webhooks.retain(|webhook| webhook.id != id);
+        if let Some(project) = db.projects.get_mut(project_name) {
+            project.touch(revision);
+        }
+        db.metadata.revision = revision;
 
         drop(db);
         self.save().await?;
+        self.notify(
+            revision,
+            ChangeKind::ProjectUpdated,
+            project_name,
+            None,
+            Vec::new(),
+        );
         Ok(())
     }
+
+    pub async fn get_webhook(&self, project_name: &str, id: &str) -> Result<Webhook> {
+        self.list_webhooks(project_name)
+            .await?
+            .into_iter()
+            .find(|webhook| webhook.id == id)
+            .ok_or_else(|| AppError::WebhookNotFound(id.to_string()))
+    }
+
+    /// The project's delivery log, oldest first.
+    pub async fn list_deliveries(&self, project_name: &str) -> Result<Vec<Delivery>> {
+        let db = self.db.read().await;
+        if !db.projects.contains_key(project_name) {
+            return Err(AppError::ProjectNotFound(project_name.to_string()));
+        }
+        Ok(db
+            .webhooks
+            .get(project_name)
+            .map(|hooks| hooks.deliveries.clone())
+            .unwrap_or_default())
+    }
+
+    /// Appends `(project, delivery)` pairs to the projects' delivery logs
+    /// in a single write, keeping the latest [`MAX_DELIVERIES`] of each.
+    /// Deliveries for projects deleted since are dropped. The log isn't
+    /// configuration, so this doesn't change any revision or notify watchers.
+    pub async fn record_deliveries(&self, deliveries: Vec<(String, Delivery)>) -> Result<()> {
+        let mut db = self.db.write().await;
+        for (project_name, delivery) in deliveries {
+            if !db.projects.contains_key(&project_name) {
+                continue;
+            }
+            let log = &mut db.webhooks.entry(project_name).or_default().deliveries;
+            log.push(delivery);
+            let excess = log.len().saturating_sub(MAX_DELIVERIES);
+            log.drain(..excess);
+        }
+
+        drop(db);
+        self.save().await
+    }
+
+    /// Checks an environment's resolved values against the project schema.
+    /// An environment that doesn't exist yet is checked as if it were empty,
+    /// and references that don't resolve are reported as violations.
+    pub async fn validate_environment(
+        &self,
+        project_name: &str,
+        env: &str,
+    ) -> Result<Vec<Violation>> {
+        let db = self.db.read().await;
+        let project = db
+            .projects
+            .get(project_name)
+            .ok_or_else(|| AppError::ProjectNotFound(project_name.to_string()))?;
+
+        let keys: Vec<String> = if project.environments.contains_key(env) {
+            resolve::merge(project, env)?.0.into_keys().collect()
+        } else {
+            Vec::new()
+        };
+
+        let mut unresolved = Vec::new();
+        let mut environment = Environment::new();
+        for key in &keys {
+            match resolve::resolve_variable(&db, project_name, env, key) {
+                Ok(var) => {
+                    environment.insert(key.clone(), var);
+                }
+                Err(e) => unresolved.push(Violation {
+                    key: key.clone(),
+                    message: e.to_string(),
+                }),
+            }
+        }
+
+        let mut violations = schema::violations(&project.schema, env, &environment);
+        // Keys that failed to resolve already have a better explanation
+        violations.retain(|violation| !unresolved.iter().any(|u| u.key == violation.key));
+        violations.extend(unresolved);
+        violations.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(violations)
+    }
+}
+
+fn revision_of(db: &Database, target: &Target) -> Result<u64> {
+    let project_name = match target {
+        Target::Project(project)
+        | Target::Environment(project, _)
+        | Target::Variable(project, _, _) => project,
+    };
+    let project = db
+        .projects
+        .get(project_name)
+        .ok_or_else(|| AppError::ProjectNotFound(project_name.clone()))?;
+
+    match target {
+        Target::Project(_) => Ok(project.revision),
+        Target::Environment(_, env) | Target::Variable(_, env, _) => {
+            let environment = project
+                .environments
+                .get(env)
+                .ok_or_else(|| AppError::EnvironmentNotFound(env.clone()))?;
+            match target {
+                Target::Variable(_, _, key) => environment
+                    .get(key)
+                    .map(|var| var.revision)
+                    .ok_or_else(|| AppError::VariableNotFound(key.clone())),
+                _ => Ok(project
+                    .environment_settings
+                    .get(env)
+                    .map_or(0, |settings| settings.revision)),
+            }
+        }
+    }
+}
+
+/// The schema's type wins; otherwise an existing or inherited variable keeps
+/// its type unless a new one is given.
+fn variable_type(
+    project: &Project,
+    env: &str,
+    key: &str,
+    requested: Option<VariableType>,
+) -> Result<VariableType> {
+    Ok(match project.schema.get(key) {
+        Some(rule) if !rule.value_type.is_string() => rule.value_type,
+        _ => match requested {
+            Some(value_type) => value_type,
+            None => resolve::lineage(project, env)?
+                .iter()
+                .find_map(|layer| project.environments.get(layer)?.get(key))
+                .map(|existing| existing.value_type)
+                .unwrap_or_default(),
+        },
+    })
+}
+
+/// Refuses to remove a key the schema requires in `env`, unless a default
+/// or an ancestor still provides it.
+fn check_removal(project: &Project, env: &str, key: &str) -> Result<()> {
+    // Deleting an override is fine while an ancestor still sets the key
+    let inherited = resolve::lineage(project, env)?.iter().skip(1).any(|layer| {
+        project
+            .environments
+            .get(layer)
+            .is_some_and(|vars| vars.contains_key(key))
+    });
+    match project.schema.get(key) {
+        Some(rule) if rule.is_required_in(env) && rule.default.is_none() && !inherited => Err(
+            AppError::SchemaViolation(format!("{} is required in {}", key, env)),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Refuses values that can't be parsed into text and references, which
+/// would otherwise be stored and only fail when read.
+fn check_references(key: &str, value: &str) -> Result<()> {
+    resolve::check_syntax(value).map_err(|reason| {
+        AppError::InvalidReference(format!(
+            "{}: {} (write $${{ for a literal ${{)",
+            key, reason
+        ))
+    })
+}
+
+/// Values with references are only checked once resolved, by
+/// `validate_environment`.
+fn validate_value(key: &str, value: &str, value_type: VariableType) -> Result<()> {
+    check_references(key, value)?;
+    if resolve::has_references(value) {
+        return Ok(());
+    }
+    value_type
+        .validate(value)
+        .map_err(|reason| AppError::InvalidValue(format!("{} ({}): {}", key, value_type, reason)))
+}
+
+/// Checks `value` against the schema rule for `key`, if there is one.
+fn check_rule(schema: &Schema, key: &str, value: &str) -> Result<()> {
+    match schema.get(key) {
+        Some(_) if resolve::has_references(value) => Ok(()),
+        Some(rule) => rule
+            .check(value)
+            .map_err(|reason| AppError::InvalidValue(format!("{}: {}", key, reason))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::VariablePatch;
+
+    async fn store() -> (JsonStore, PathBuf) {
+        let path = std::env::temp_dir().join(format!("rusty-{}.json", uuid::Uuid::new_v4()));
+        let store = JsonStore::new(path.clone()).unwrap();
+        store.create_project("app".to_string(), None).await.unwrap();
+        (store, path)
+    }
+
+    #[tokio::test]
+    async fn refuses_unparseable_references_on_every_write() {
+        let (store, path) = store().await;
+        let bad = || "p${ss".to_string();
+        let refused = |result: Result<()>| {
+            assert!(
+                matches!(result, Err(AppError::InvalidReference(_))),
+                "{:?}",
+                result
+            )
+        };
+
+        refused(
+            store
+                .set_variable("app", "dev", "PASS".to_string(), bad(), false, None)
+                .await
+                .map(drop),
+        );
+        refused(
+            store
+                .patch_environment(
+                    "app",
+                    "dev",
+                    [("PASS".to_string(), Some(VariablePatch::Value(bad())))].into(),
+                )
+                .await
+                .map(drop),
+        );
+        refused(
+            store
+                .import_variables(
+                    "app",
+                    "dev",
+                    vec![("PASS".to_string(), bad())],
+                    ImportOptions::default(),
+                )
+                .await
+                .map(drop),
+        );
+        refused(
+            store
+                .set_schema_rule(
+                    "app",
+                    "PASS".to_string(),
+                    SchemaRule {
+                        default: Some(bad()),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .map(drop),
+        );
+
+        // `$${` is the way to store a literal `${`
+        store
+            .set_variable(
+                "app",
+                "dev",
+                "PASS".to_string(),
+                "p$${ss".to_string(),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_environment("app", "dev").await.unwrap()["PASS"].value,
+            "p${ss"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_stored_unparseable_values_literally() {
+        let (store, path) = store().await;
+        store
+            .set_variable(
+                "app",
+                "dev",
+                "PORT".to_string(),
+                "80".to_string(),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        // As stored by a version that didn't check references on write
+        store
+            .db
+            .write()
+            .await
+            .projects
+            .get_mut("app")
+            .unwrap()
+            .environments
+            .get_mut("dev")
+            .unwrap()
+            .insert(
+                "PASS".to_string(),
+                EnvVariable::new("p${ss".to_string(), true),
+            );
+
+        let env = store.get_environment("app", "dev").await.unwrap();
+        assert_eq!(env["PASS"].value, "p${ss");
+        assert_eq!(env["PORT"].value, "80");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn watch_index_follows_references_and_schema_defaults() {
+        let (store, path) = store().await;
+        store
+            .create_project("other".to_string(), None)
+            .await
+            .unwrap();
+        let set = |project: &'static str, env: &'static str, key: &str, value: &str| {
+            let store = store.clone();
+            let (key, value) = (key.to_string(), value.to_string());
+            async move {
+                store
+                    .set_variable(project, env, key, value, false, None)
+                    .await
+                    .unwrap();
+            }
+        };
+        set("app", "shared", "HOST", "a").await;
+        set("other", "prod", "TOKEN", "t").await;
+        set("app", "dev", "URL", "${shared:HOST}").await;
+        set("app", "dev", "AUTH", "${other/prod:TOKEN}").await;
+
+        let mut index = store.watch_index("app", "dev").await.unwrap();
+        let mut changed = async || {
+            let current = store.watch_index("app", "dev").await.unwrap();
+            std::mem::replace(&mut index, current) != current
+        };
+
+        set("app", "unrelated", "X", "1").await;
+        assert!(!changed().await);
+        set("app", "shared", "HOST", "b").await;
+        assert!(changed().await);
+        set("other", "prod", "TOKEN", "u").await;
+        assert!(changed().await);
+        store
+            .set_schema_rule(
+                "app",
+                "LOG_LEVEL".to_string(),
+                SchemaRule {
+                    default: Some("info".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(changed().await);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn renames_check_stored_protection_and_follow_links() {
+        let (store, path) = store().await;
+        let create = |env: &'static str, settings: EnvironmentSettings| {
+            let store = store.clone();
+            async move {
+                store
+                    .create_environment("app", env, settings, None)
+                    .await
+                    .unwrap();
+            }
+        };
+        create(
+            "prod",
+            EnvironmentSettings {
+                protected: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        create("staging", EnvironmentSettings::default()).await;
+        create(
+            "preview",
+            EnvironmentSettings {
+                parent: Some("staging".to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+        let webhook = Webhook::new(
+            "https://hooks.example.com/x".to_string(),
+            None,
+            Vec::new(),
+            vec!["staging".to_string()],
+        )
+        .unwrap();
+        store.add_webhook("app", webhook).await.unwrap();
+        store
+            .set_schema_rule(
+                "app",
+                "PORT".to_string(),
+                SchemaRule {
+                    required_in: vec!["staging".to_string(), "prod".to_string()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let result = store
+            .update_environment("app", "prod", Some("live".to_string()), None, Some(false))
+            .await;
+        assert!(
+            matches!(result, Err(AppError::EnvironmentProtected(_))),
+            "{:?}",
+            result
+        );
+        assert!(store.get_project("app").await.unwrap().environment_settings["prod"].protected);
+
+        store
+            .update_environment("app", "staging", Some("stage".to_string()), None, None)
+            .await
+            .unwrap();
+        let project = store.get_project("app").await.unwrap();
+        assert_eq!(
+            project.environment_settings["preview"].parent.as_deref(),
+            Some("stage")
+        );
+        assert_eq!(
+            store.list_webhooks("app").await.unwrap()[0].environments,
+            ["stage"]
+        );
+        assert_eq!(project.schema["PORT"].required_in, ["stage", "prod"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_renames_that_would_leave_references_dangling() {
+        let (store, path) = store().await;
+        store
+            .create_project("other".to_string(), None)
+            .await
+            .unwrap();
+        for (project, env, key, value) in [
+            ("app", "staging", "HOST", "db"),
+            ("app", "prod", "URL", "${staging:HOST}"),
+            ("other", "prod", "HOST", "${app/staging:HOST}"),
+        ] {
+            store
+                .set_variable(
+                    project,
+                    env,
+                    key.to_string(),
+                    value.to_string(),
+                    false,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        let err = store
+            .update_environment("app", "staging", Some("stage".to_string()), None, None)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("referred to by app/prod:URL, other/prod:HOST"),
+            "{}",
+            err
+        );
+        assert!(store.get_environment("app", "staging").await.is_ok());
+
+        // Once nothing names it, the rename goes through
+        for (project, env, key) in [("app", "prod", "URL"), ("other", "prod", "HOST")] {
+            store.delete_variable(project, env, key).await.unwrap();
+        }
+        store
+            .update_environment("app", "staging", Some("stage".to_string()), None, None)
+            .await
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn imports_type_new_keys_like_set() {
+        let (store, path) = store().await;
+        store
+            .set_variable(
+                "app",
+                "base",
+                "PORT".to_string(),
+                "8080".to_string(),
+                false,
+                Some(VariableType::Port),
+            )
+            .await
+            .unwrap();
+        store
+            .create_environment(
+                "app",
+                "staging",
+                EnvironmentSettings {
+                    parent: Some("base".to_string()),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        let import = |value: &str| {
+            store.import_variables(
+                "app",
+                "staging",
+                vec![("PORT".to_string(), value.to_string())],
+                ImportOptions::default(),
+            )
+        };
+
+        let result = import("not-a-port").await;
+        assert!(
+            matches!(result, Err(AppError::InvalidValue(_))),
+            "{:?}",
+            result
+        );
+        import("9090").await.unwrap();
+        let raw = store.get_raw_environment("app", "staging").await.unwrap();
+        assert_eq!(raw["PORT"].value_type, VariableType::Port);
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -0,0 +1,193 @@
+use crate::models::{EnvVariable, Environment};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// How encrypted values appear in a diff. They are always compared by
+/// their real value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretDisplay {
+    /// Replace with ***
+    #[default]
+    Mask,
+    /// Show a short SHA-256 fingerprint, so equal secrets can be recognised
+    Hash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// Only on the right
+    Added,
+    /// Only on the left
+    Removed,
+    Changed,
+}
+
+impl Change {
+    pub fn marker(self) -> char {
+        match self {
+            Self::Added => '+',
+            Self::Removed => '-',
+            Self::Changed => '~',
+        }
+    }
+}
+
+/// One differing key, with display values that are already masked.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffEntry {
+    pub key: String,
+    pub change: Change,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub right: Option<String>,
+}
+
+/// Keys that differ between `left` and `right`, sorted by key. A key
+/// encrypted on either side is displayed according to `secrets`.
+pub fn diff(left: &Environment, right: &Environment, secrets: SecretDisplay) -> Vec<DiffEntry> {
+    let mut keys: Vec<&String> = left.keys().chain(right.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let (l, r) = (left.get(key), right.get(key));
+            let change = match (l, r) {
+                (Some(l), Some(r)) if l.value == r.value => return None,
+                (Some(_), Some(_)) => Change::Changed,
+                (Some(_), None) => Change::Removed,
+                (None, Some(_)) => Change::Added,
+                (None, None) => return None,
+            };
+            let secret = l.is_some_and(|var| var.encrypted) || r.is_some_and(|var| var.encrypted);
+            let show = |var: &EnvVariable| display(&var.value, secret, secrets);
+            Some(DiffEntry {
+                key: key.clone(),
+                change,
+                left: l.map(show),
+                right: r.map(show),
+            })
+        })
+        .collect()
+}
+
+/// Whether `key` matches one of `include` (or `include` is empty) and none
+/// of `exclude`.
+pub fn selected(key: &str, include: &[String], exclude: &[String]) -> bool {
+    (include.is_empty() || include.iter().any(|pattern| glob_match(pattern, key)))
+        && !exclude.iter().any(|pattern| glob_match(pattern, key))
+}
+
+/// Shell-style matching where `*` is any run of characters and `?` is one.
+fn glob_match(pattern: &str, key: &str) -> bool {
+    let (pattern, key): (Vec<char>, Vec<char>) = (pattern.chars().collect(), key.chars().collect());
+    let (mut p, mut k) = (0, 0);
+    // Where to resume after the last `*` if the rest fails to match
+    let mut backtrack = None;
+    while k < key.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, k));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match backtrack {
+                Some((star, from)) => {
+                    p = star + 1;
+                    k = from + 1;
+                    backtrack = Some((star, from + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn display(value: &str, secret: bool, secrets: SecretDisplay) -> String {
+    match (secret, secrets) {
+        (false, _) => value.to_string(),
+        (true, SecretDisplay::Mask) => "***".to_string(),
+        (true, SecretDisplay::Hash) => {
+            let digest = Sha256::digest(value.as_bytes());
+            let hex: String = digest
+                .iter()
+                .take(6)
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            format!("sha256:{}", hex)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn environment(vars: &[(&str, &str, bool)]) -> Environment {
+        vars.iter()
+            .map(|(k, v, e)| (k.to_string(), EnvVariable::new(v.to_string(), *e)))
+            .collect()
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_keys() {
+        let left = environment(&[("A", "1", false), ("B", "2", false), ("S", "old", true)]);
+        let right = environment(&[("B", "3", false), ("C", "4", false), ("S", "new", false)]);
+
+        let entries = diff(&left, &right, SecretDisplay::Mask);
+        let summary: Vec<(&str, Change, Option<&str>, Option<&str>)> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.key.as_str(),
+                    e.change,
+                    e.left.as_deref(),
+                    e.right.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("A", Change::Removed, Some("1"), None),
+                ("B", Change::Changed, Some("2"), Some("3")),
+                ("C", Change::Added, None, Some("4")),
+                ("S", Change::Changed, Some("***"), Some("***")),
+            ]
+        );
+    }
+
+    #[test]
+    fn globs_select_keys() {
+        let include = ["DB_*".to_string(), "PORT".to_string()];
+        let exclude = ["*_PASSWORD".to_string()];
+        assert!(selected("DB_HOST", &include, &exclude));
+        assert!(selected("PORT", &include, &exclude));
+        assert!(!selected("DB_PASSWORD", &include, &exclude));
+        assert!(!selected("PORTS", &include, &exclude));
+        assert!(selected("ANYTHING", &[], &[]));
+        assert!(glob_match("A?C*D", "ABCxxD") && !glob_match("A?C*D", "ACxD"));
+    }
+
+    #[test]
+    fn hashes_let_equal_secrets_be_recognised() {
+        let left = environment(&[("S", "same", true), ("T", "a", true)]);
+        let right = environment(&[("S", "same", true), ("T", "b", true)]);
+
+        let entries = diff(&left, &right, SecretDisplay::Hash);
+        assert_eq!(entries.len(), 1);
+        let (l, r) = (
+            entries[0].left.as_ref().unwrap(),
+            entries[0].right.as_ref().unwrap(),
+        );
+        assert!(l.starts_with("sha256:") && l.len() == 19);
+        assert_ne!(l, r);
+    }
+}
@@ -1,7 +1,7 @@
 use axum::{
+    Json,
     http::StatusCode,
     response::{IntoResponse, Response},
-    Json,
 };
 use serde_json::json;
 use thiserror::Error;
@@ -17,9 +17,36 @@ pub enum AppError {
     #[error("Variable not found: {0}")]
     VariableNotFound(String),
 
+    #[error("Webhook not found: {0}")]
+    WebhookNotFound(String),
+
     #[error("Project already exists: {0}")]
     ProjectAlreadyExists(String),
 
+    #[error("Environment already exists: {0}")]
+    EnvironmentAlreadyExists(String),
+
+    #[error("Environment is protected: {0}")]
+    EnvironmentProtected(String),
+
+    #[error("Environment is in use: {0}")]
+    EnvironmentInUse(String),
+
+    #[error("Conflicting variables: {0}")]
+    VariableConflict(String),
+
+    #[error("Invalid value for {0}")]
+    InvalidValue(String),
+
+    #[error("Schema violation: {0}")]
+    SchemaViolation(String),
+
+    #[error("Invalid reference: {0}")]
+    InvalidReference(String),
+
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+
     #[allow(dead_code)]
     #[error("Database error: {0}")]
     DatabaseError(String),
@@ -35,6 +62,9 @@ pub enum AppError {
 
     #[error("Configuration error: {0}")]
     ConfigError(String),
+
+    #[error("Server request failed: {0}")]
+    ServerError(String),
 }
 
 impl IntoResponse for AppError {
@@ -43,12 +73,22 @@ impl IntoResponse for AppError {
             AppError::ProjectNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::EnvironmentNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::VariableNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            AppError::WebhookNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::ProjectAlreadyExists(_) => (StatusCode::CONFLICT, self.to_string()),
+            AppError::EnvironmentAlreadyExists(_) => (StatusCode::CONFLICT, self.to_string()),
+            AppError::EnvironmentProtected(_) => (StatusCode::CONFLICT, self.to_string()),
+            AppError::EnvironmentInUse(_) => (StatusCode::CONFLICT, self.to_string()),
+            AppError::VariableConflict(_) => (StatusCode::CONFLICT, self.to_string()),
+            AppError::InvalidValue(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
+            AppError::SchemaViolation(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
+            AppError::InvalidReference(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
+            AppError::PreconditionFailed(_) => (StatusCode::PRECONDITION_FAILED, self.to_string()),
             AppError::InvalidInput(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             AppError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             AppError::IoError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             AppError::JsonError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             AppError::ConfigError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            AppError::ServerError(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
         };
 
         let body = Json(json!({
@@ -0,0 +1,160 @@
+use super::{ExportContext, Exporter, sorted};
+use crate::error::{AppError, Result};
+use crate::models::Environment;
+
+/// Lines to append to `$GITHUB_ENV`; multiline values use a heredoc with a
+/// random delimiter that cannot occur in the value.
+pub struct GithubEnv;
+
+impl Exporter for GithubEnv {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn description(&self) -> &'static str {
+        "GitHub Actions $GITHUB_ENV file (pair with github-mask)"
+    }
+
+    fn export(&self, env: &Environment, _ctx: &ExportContext) -> Result<String> {
+        sorted(env)
+            .into_iter()
+            .map(|(key, var)| {
+                if key.is_empty() || key.contains(['=', '\n', '\r']) || key.contains("<<") {
+                    return Err(AppError::InvalidInput(format!(
+                        "{} is not a valid GitHub Actions variable name",
+                        key
+                    )));
+                }
+                if !var.value.contains(['\n', '\r']) {
+                    return Ok(format!("{}={}", key, var.value));
+                }
+
+                let delimiter = loop {
+                    let candidate = format!("ghadelimiter_{}", uuid::Uuid::new_v4());
+                    if !var.value.contains(&candidate) {
+                        break candidate;
+                    }
+                };
+                Ok(format!(
+                    "{}<<{}\n{}\n{}",
+                    key, delimiter, var.value, delimiter
+                ))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|lines| lines.join("\n"))
+    }
+}
+
+/// `::add-mask::` workflow commands for every encrypted value, to print in
+/// a step before the values reach the environment.
+pub struct GithubMask;
+
+impl Exporter for GithubMask {
+    fn name(&self) -> &'static str {
+        "github-mask"
+    }
+
+    fn description(&self) -> &'static str {
+        "GitHub Actions ::add-mask:: commands for encrypted variables"
+    }
+
+    fn export(&self, env: &Environment, _ctx: &ExportContext) -> Result<String> {
+        Ok(sorted(env)
+            .into_iter()
+            .filter(|(_, var)| var.encrypted)
+            // The runner masks line by line, so each line of a value is
+            // registered separately
+            .flat_map(|(_, var)| var.value.lines().map(str::to_string).collect::<Vec<_>>())
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| format!("::add-mask::{}", escape_command_data(&line)))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+/// Workflow command data escaping as done by `@actions/core`.
+fn escape_command_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// A GitLab CI `artifacts:reports:dotenv` file. GitLab reads values
+/// verbatim and rejects multiline values, so they are refused here.
+pub struct GitlabDotenv;
+
+impl Exporter for GitlabDotenv {
+    fn name(&self) -> &'static str {
+        "gitlab"
+    }
+
+    fn description(&self) -> &'static str {
+        "GitLab CI dotenv report artifact"
+    }
+
+    fn export(&self, env: &Environment, _ctx: &ExportContext) -> Result<String> {
+        sorted(env)
+            .into_iter()
+            .map(|(key, var)| {
+                if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(AppError::InvalidInput(format!(
+                        "{} is not a valid GitLab CI variable name",
+                        key
+                    )));
+                }
+                if var.value.contains(['\n', '\r']) {
+                    return Err(AppError::InvalidInput(format!(
+                        "{}: GitLab dotenv reports do not support multiline values",
+                        key
+                    )));
+                }
+                Ok(format!("{}={}", key, var.value))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|lines| lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvVariable;
+
+    fn environment() -> Environment {
+        [("CERT", "line1\nline2 100%", true), ("PORT", "8080", false)]
+            .iter()
+            .map(|(k, v, e)| (k.to_string(), EnvVariable::new(v.to_string(), *e)))
+            .collect()
+    }
+
+    #[test]
+    fn github_env_uses_heredocs_for_multiline_values() {
+        let output = GithubEnv
+            .export(&environment(), &ExportContext::default())
+            .unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        let delimiter = lines[0].strip_prefix("CERT<<").unwrap();
+        assert!(delimiter.starts_with("ghadelimiter_"));
+        assert_eq!(&lines[1..4], ["line1", "line2 100%", delimiter]);
+        assert_eq!(lines[4], "PORT=8080");
+    }
+
+    #[test]
+    fn github_mask_covers_each_line_of_encrypted_values() {
+        let output = GithubMask
+            .export(&environment(), &ExportContext::default())
+            .unwrap();
+        assert_eq!(output, "::add-mask::line1\n::add-mask::line2 100%25");
+    }
+
+    #[test]
+    fn gitlab_rejects_multiline_values() {
+        assert!(
+            GitlabDotenv
+                .export(&environment(), &ExportContext::default())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use super::{ExportContext, Exporter, ImportContext, Importer, Variables, is_plain, sorted};
+use crate::error::{AppError, Result};
+use crate::models::Environment;
+
+/// `docker run -e` arguments on export; arguments or `--env-file` on import.
+pub struct Docker;
+
+impl Exporter for Docker {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn description(&self) -> &'static str {
+        "docker run -e arguments / docker --env-file"
+    }
+
+    /// Each argument is shell-quoted so the output can be `eval`ed.
+    fn export(&self, env: &Environment, _ctx: &ExportContext) -> Result<String> {
+        Ok(sorted(env)
+            .into_iter()
+            .map(|(key, var)| {
+                let arg = format!("{}={}", key, var.value);
+                if is_plain(key) && is_plain(&var.value) {
+                    format!("-e {}", arg)
+                } else {
+                    format!("-e {}", crate::shell::quote_posix(&arg))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+}
+
+impl Importer for Docker {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn description(&self) -> &'static str {
+        "docker run -e arguments / docker --env-file"
+    }
+
+    fn import(&self, input: &str, _ctx: &ImportContext) -> Result<Variables> {
+        parse(input)
+    }
+}
+
+/// Parses either `docker run` arguments as produced by `export_docker`
+/// (`-e 'KEY=value' ...`) or a docker `--env-file` (one literal `KEY=value`
+/// per line, `#` comments, no quoting). A bare `KEY` line, which docker
+/// fills from the host, is refused: the host here may be the server.
+fn parse(input: &str) -> Result<Variables> {
+    let trimmed = input.trim_start();
+    if trimmed.starts_with("-e ") || trimmed.starts_with("--env ") {
+        return parse_docker_args(trimmed);
+    }
+
+    let mut vars = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line.trim_start();
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').ok_or_else(|| {
+            AppError::InvalidInput(format!(
+                "line {}: expected KEY=value, got {}",
+                number + 1,
+                line.trim()
+            ))
+        })?;
+        vars.push((key.trim().to_string(), value.to_string()));
+    }
+
+    Ok(vars)
+}
+
+fn parse_docker_args(input: &str) -> Result<Variables> {
+    let words = shell_words(input)?;
+    let mut vars = Vec::new();
+    let mut words = words.into_iter();
+
+    while let Some(flag) = words.next() {
+        if flag != "-e" && flag != "--env" {
+            return Err(AppError::InvalidInput(format!(
+                "unexpected argument: {}",
+                flag
+            )));
+        }
+        let arg = words
+            .next()
+            .ok_or_else(|| AppError::InvalidInput(format!("{} needs a value", flag)))?;
+        let (key, value) = arg
+            .split_once('=')
+            .ok_or_else(|| AppError::InvalidInput(format!("expected KEY=value, got {}", arg)))?;
+        vars.push((key.to_string(), value.to_string()));
+    }
+
+    Ok(vars)
+}
+
+/// Splits POSIX shell words made of bare, single-quoted, double-quoted and
+/// backslash-escaped parts.
+fn shell_words(input: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = input.chars();
+    let unterminated = || AppError::InvalidInput("unterminated quote".to_string());
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '"' => break,
+                        '\\' => {
+                            let escaped = chars.next().ok_or_else(unterminated)?;
+                            if !matches!(escaped, '\\' | '"' | '$' | '`' | '\n') {
+                                word.push('\\');
+                            }
+                            word.push(escaped);
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn docker_env_file_is_literal() {
+        let input = "# comment\nA=\"quoted\"\nB=x y\n";
+        assert_eq!(
+            parse(input).unwrap(),
+            vec![
+                ("A".to_string(), "\"quoted\"".to_string()),
+                ("B".to_string(), "x y".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn bare_keys_are_refused_not_read_from_the_host() {
+        // PATH is set in any process running the tests
+        let err = parse("A=1\nPATH\n").unwrap_err().to_string();
+        assert!(
+            err.contains("line 2: expected KEY=value, got PATH"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use super::{ExportContext, Exporter, ImportContext, Importer, Variables, is_plain, sorted};
+use crate::error::{AppError, Result};
+use crate::models::Environment;
+
+/// `KEY=value` lines as read by dotenv libraries and docker compose.
+pub struct Dotenv;
+
+impl Exporter for Dotenv {
+    fn name(&self) -> &'static str {
+        "dotenv"
+    }
+
+    fn description(&self) -> &'static str {
+        "KEY=value lines (.env files)"
+    }
+
+    fn export(&self, env: &Environment, _ctx: &ExportContext) -> Result<String> {
+        Ok(sorted(env)
+            .into_iter()
+            .map(|(key, var)| format!("{}={}", key, quote(&var.value)))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+impl Importer for Dotenv {
+    fn name(&self) -> &'static str {
+        "dotenv"
+    }
+
+    fn description(&self) -> &'static str {
+        "KEY=value lines (.env files)"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["env"]
+    }
+
+    fn import(&self, input: &str, _ctx: &ImportContext) -> Result<Variables> {
+        parse(input)
+    }
+}
+
+/// Single quotes are literal in dotenv; values that contain one or a line
+/// break fall back to double quotes with backslash escapes.
+fn quote(value: &str) -> String {
+    if is_plain(value) {
+        return value.to_string();
+    }
+    if !value.contains(['\'', '\n', '\r']) {
+        return format!("'{}'", value);
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str(r"\\"),
+            '"' => quoted.push_str("\\\""),
+            '$' => quoted.push_str(r"\$"),
+            '`' => quoted.push_str(r"\`"),
+            '\n' => quoted.push_str(r"\n"),
+            '\r' => quoted.push_str(r"\r"),
+            '\t' => quoted.push_str(r"\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parses `KEY=value` lines with optional `export ` prefixes, `#` comments,
+/// single-quoted literals and double-quoted values with backslash escapes.
+/// Quoted values may span several lines.
+fn parse(input: &str) -> Result<Variables> {
+    let mut vars = Vec::new();
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let line_no = input[..pos].matches('\n').count() + 1;
+        let end = line_end(input, pos);
+        let line = input[pos..end].trim();
+        if line.is_empty() || line.starts_with('#') {
+            pos = end + 1;
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, raw) = line.split_once('=').ok_or_else(|| {
+            AppError::InvalidInput(format!("line {}: expected KEY=value", line_no))
+        })?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(AppError::InvalidInput(format!(
+                "line {}: empty key",
+                line_no
+            )));
+        }
+
+        let raw = raw.trim_start();
+        let value = match raw.chars().next() {
+            Some(quote @ ('\'' | '"')) => {
+                // `raw` borrows from `input`, so its offset locates the quote
+                let start = raw.as_ptr() as usize - input.as_ptr() as usize + 1;
+                let (value, consumed) = unquote(&input[start..], quote).ok_or_else(|| {
+                    AppError::InvalidInput(format!(
+                        "line {}: unterminated {} quote",
+                        line_no, quote
+                    ))
+                })?;
+
+                let close = start + consumed;
+                let end = line_end(input, close);
+                let trailing = input[close..end].trim();
+                if !trailing.is_empty() && !trailing.starts_with('#') {
+                    return Err(AppError::InvalidInput(format!(
+                        "line {}: unexpected characters after closing quote",
+                        line_no
+                    )));
+                }
+                pos = end + 1;
+                value
+            }
+            _ => {
+                pos = end + 1;
+                match raw.find(" #") {
+                    Some(comment) => raw[..comment].trim_end().to_string(),
+                    None => raw.trim_end().to_string(),
+                }
+            }
+        };
+
+        vars.push((key.to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+/// Index of the newline ending the line that contains `pos`, or the end of input.
+fn line_end(input: &str, pos: usize) -> usize {
+    input[pos..].find('\n').map_or(input.len(), |i| pos + i)
+}
+
+/// Reads a quoted value up to its closing `quote`, returning the value and
+/// the number of bytes consumed including the closing quote.
+fn unquote(input: &str, quote: char) -> Option<(String, usize)> {
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        if c == quote {
+            return Some((value, i + c.len_utf8()));
+        }
+        if c == '\\' && quote == '"' {
+            let (_, escaped) = chars.next()?;
+            match escaped {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                '\\' | '"' | '$' | '`' => value.push(escaped),
+                other => {
+                    value.push('\\');
+                    value.push(other);
+                }
+            }
+            continue;
+        }
+        value.push(c);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(vars: &[(&str, &str)]) -> Variables {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn dotenv_handles_comments_exports_and_multiline_quotes() {
+        let input = "# comment\n\nexport A=1\nB = two # trailing\nC='x # y'\nD=\"line1\nline2\" # note\r\nE=\"esc\\n\\\"q\\\"\"\n";
+        assert_eq!(
+            parse(input).unwrap(),
+            pairs(&[
+                ("A", "1"),
+                ("B", "two"),
+                ("C", "x # y"),
+                ("D", "line1\nline2"),
+                ("E", "esc\n\"q\""),
+            ])
+        );
+    }
+
+    #[test]
+    fn dotenv_rejects_unterminated_quotes() {
+        assert!(parse("A=\"open\nB=1\n").is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use super::{
+    ExportContext, Exporter, ImportContext, Importer, Tree, Variables, flat_key, nest, sorted,
+};
+use crate::error::{AppError, Result};
+use crate::models::Environment;
+use serde_json::{Map, Value};
+
+/// A JSON object, nested when `separator=` is given. Values are strings
+/// unless the variable is typed, so a `port` exports as a number.
+pub struct Json;
+
+impl Exporter for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn description(&self) -> &'static str {
+        "JSON object, flat or nested"
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[("separator", "Nest keys split on this separator, e.g. __")]
+    }
+
+    fn export(&self, env: &Environment, ctx: &ExportContext) -> Result<String> {
+        let object: Map<String, Value> = match ctx.option("separator") {
+            Some(separator) => nest(env, separator)?
+                .into_iter()
+                .map(|(key, tree)| (key.to_string(), to_value(tree)))
+                .collect(),
+            None => sorted(env)
+                .into_iter()
+                .map(|(key, var)| (key.to_string(), var.value_type.to_json(&var.value)))
+                .collect(),
+        };
+        serde_json::to_string_pretty(&object).map_err(Into::into)
+    }
+}
+
+impl Importer for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn description(&self) -> &'static str {
+        "JSON object, flat or nested"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[(
+            "separator",
+            "Join nested keys with this separator (default: __)",
+        )]
+    }
+
+    fn import(&self, input: &str, ctx: &ImportContext) -> Result<Variables> {
+        parse(input, ctx.separator())
+    }
+}
+
+fn to_value(tree: Tree) -> Value {
+    match tree {
+        Tree::Value(var) => var.value_type.to_json(&var.value),
+        Tree::Table(children) => Value::Object(
+            children
+                .into_iter()
+                .map(|(key, tree)| (key.to_string(), to_value(tree)))
+                .collect(),
+        ),
+    }
+}
+
+/// Parses a JSON object; numbers, booleans and null become strings, and
+/// nested objects and arrays are flattened into `PARENT{separator}CHILD`.
+fn parse(input: &str, separator: &str) -> Result<Variables> {
+    let value: Value = serde_json::from_str(input)?;
+    if !value.is_object() {
+        return Err(AppError::InvalidInput("expected a JSON object".to_string()));
+    }
+
+    let mut vars = Vec::new();
+    flatten(String::new(), &value, separator, &mut vars);
+    Ok(vars)
+}
+
+fn flatten(prefix: String, value: &Value, separator: &str, vars: &mut Variables) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten(flat_key(&prefix, key, separator), value, separator, vars);
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(
+                    flat_key(&prefix, &i.to_string(), separator),
+                    value,
+                    separator,
+                    vars,
+                );
+            }
+        }
+        Value::String(s) => vars.push((prefix, s.clone())),
+        Value::Null => vars.push((prefix, String::new())),
+        Value::Bool(_) | Value::Number(_) => vars.push((prefix, value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scalars_become_strings() {
+        assert_eq!(
+            parse(r#"{"PORT": 8080, "FLAG": true, "EMPTY": null}"#, "__").unwrap(),
+            vec![
+                ("EMPTY".to_string(), String::new()),
+                ("FLAG".to_string(), "true".to_string()),
+                ("PORT".to_string(), "8080".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_values_are_flattened() {
+        assert_eq!(
+            parse(r#"{"A": {"B": 1, "C": ["x", "y"]}}"#, ":").unwrap(),
+            vec![
+                ("A:B".to_string(), "1".to_string()),
+                ("A:C:0".to_string(), "x".to_string()),
+                ("A:C:1".to_string(), "y".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use super::yaml::{key_of, quote};
+use super::{ExportContext, Exporter, sorted};
+use crate::error::{AppError, Result};
+use crate::models::Environment;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// A Kubernetes `Secret` holding encrypted variables and a `ConfigMap`
+/// holding the rest, as one multi-document YAML stream.
+pub struct Kubernetes;
+
+impl Exporter for Kubernetes {
+    fn name(&self) -> &'static str {
+        "k8s"
+    }
+
+    fn description(&self) -> &'static str {
+        "Kubernetes Secret and ConfigMap manifests"
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("name", "Resource name (default: <project>-<environment>)"),
+            ("namespace", "Resource namespace"),
+            ("labels", "Extra labels as key=value,key=value"),
+        ]
+    }
+
+    fn export(&self, env: &Environment, ctx: &ExportContext) -> Result<String> {
+        let name = match ctx.option("name") {
+            Some(name) => name.to_string(),
+            None => resource_name(&format!("{}-{}", ctx.project, ctx.environment)),
+        };
+        let metadata = metadata(&name, ctx)?;
+
+        let mut secret = Vec::new();
+        let mut config = Vec::new();
+        for (key, var) in sorted(env) {
+            if !is_valid_key(key) {
+                return Err(AppError::InvalidInput(format!(
+                    "{} is not a valid Secret or ConfigMap key",
+                    key
+                )));
+            }
+            if var.encrypted {
+                secret.push(format!(
+                    "  {}: {}",
+                    key_of(key),
+                    STANDARD.encode(&var.value)
+                ));
+            } else {
+                config.push(format!("  {}: {}", key_of(key), quote(&var.value)));
+            }
+        }
+
+        let mut documents = Vec::new();
+        if !secret.is_empty() {
+            documents.push(format!(
+                "apiVersion: v1\nkind: Secret\n{}type: Opaque\ndata:\n{}",
+                metadata,
+                secret.join("\n")
+            ));
+        }
+        if !config.is_empty() || secret.is_empty() {
+            let data = if config.is_empty() {
+                "data: {}".to_string()
+            } else {
+                format!("data:\n{}", config.join("\n"))
+            };
+            documents.push(format!(
+                "apiVersion: v1\nkind: ConfigMap\n{}{}",
+                metadata, data
+            ));
+        }
+
+        Ok(documents.join("\n---\n"))
+    }
+}
+
+fn metadata(name: &str, ctx: &ExportContext) -> Result<String> {
+    let mut labels = vec![
+        ("app.kubernetes.io/name".to_string(), ctx.project.clone()),
+        (
+            "app.kubernetes.io/managed-by".to_string(),
+            "rusty".to_string(),
+        ),
+        ("rusty/environment".to_string(), ctx.environment.clone()),
+    ];
+    for pair in ctx
+        .option("labels")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+    {
+        let (key, value) = pair.split_once('=').ok_or_else(|| {
+            AppError::InvalidInput(format!("labels: expected key=value, got {}", pair))
+        })?;
+        labels.retain(|(existing, _)| existing != key.trim());
+        labels.push((key.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut out = format!("metadata:\n  name: {}\n", quote(name));
+    if let Some(namespace) = ctx.option("namespace") {
+        out.push_str(&format!("  namespace: {}\n", quote(namespace)));
+    }
+    out.push_str("  labels:\n");
+    for (key, value) in labels {
+        out.push_str(&format!("    {}: {}\n", quote(&key), quote(&value)));
+    }
+    Ok(out)
+}
+
+/// Lowercases `raw` and replaces anything outside DNS-1123 with `-`.
+fn resource_name(raw: &str) -> String {
+    let name: String = raw
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    name.trim_matches(|c| c == '-' || c == '.')
+        .chars()
+        .take(253)
+        .collect()
+}
+
+/// Secret and ConfigMap keys may only use `[-._a-zA-Z0-9]`.
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvVariable;
+
+    fn context(options: &[(&str, &str)]) -> ExportContext {
+        ExportContext {
+            project: "My_App".to_string(),
+            environment: "production".to_string(),
+            options: options
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn splits_secrets_from_config() {
+        let env: Environment = [("API_TOKEN", "s3cr3t", true), ("PORT", "8080", false)]
+            .iter()
+            .map(|(k, v, e)| (k.to_string(), EnvVariable::new(v.to_string(), *e)))
+            .collect();
+
+        let output = Kubernetes
+            .export(
+                &env,
+                &context(&[("namespace", "prod"), ("labels", "team=web")]),
+            )
+            .unwrap();
+        let docs: Vec<&str> = output.split("\n---\n").collect();
+        assert_eq!(
+            yaml_rust2::YamlLoader::load_from_str(&output)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        assert_eq!(docs.len(), 2);
+        assert!(docs[0].contains("kind: Secret"));
+        assert!(docs[0].contains("  API_TOKEN: czNjcjN0"));
+        assert!(docs[0].contains("  name: \"my-app-production\""));
+        assert!(docs[0].contains("  namespace: \"prod\""));
+        assert!(docs[0].contains("    \"team\": \"web\""));
+        assert!(docs[1].contains("kind: ConfigMap"));
+        assert!(docs[1].contains("  PORT: \"8080\""));
+        assert!(!docs[1].contains("API_TOKEN"));
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let env: Environment = [(
+            "BAD KEY".to_string(),
+            EnvVariable::new("x".to_string(), false),
+        )]
+        .into_iter()
+        .collect();
+        assert!(Kubernetes.export(&env, &context(&[])).is_err());
+    }
+}
@@ -0,0 +1,525 @@
+mod ci;
+mod docker;
+mod dotenv;
+mod json;
+mod k8s;
+mod shell;
+mod toml;
+mod yaml;
+
+use crate::error::{AppError, Result};
+use crate::models::{EnvVariable, Environment};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Parsed `(key, value)` pairs in document order.
+pub type Variables = Vec<(String, String)>;
+
+/// Where the variables being exported come from, plus format-specific
+/// options such as `namespace=prod`.
+#[derive(Default)]
+pub struct ExportContext {
+    pub project: String,
+    pub environment: String,
+    pub options: BTreeMap<String, String>,
+}
+
+impl ExportContext {
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+}
+
+/// Format-specific options for an import, such as `separator=.`.
+#[derive(Default)]
+pub struct ImportContext {
+    pub options: BTreeMap<String, String>,
+}
+
+impl ImportContext {
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    /// The separator nested documents are flattened with.
+    fn separator(&self) -> &str {
+        self.option("separator").unwrap_or(DEFAULT_SEPARATOR)
+    }
+}
+
+/// Renders an environment in some output format.
+pub trait Exporter: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn export(&self, env: &Environment, ctx: &ExportContext) -> Result<String>;
+
+    /// Options this format accepts, as `(name, description)`.
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+}
+
+/// Reads variables from some input format.
+pub trait Importer: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn import(&self, input: &str, ctx: &ImportContext) -> Result<Variables>;
+
+    /// File extensions that imply this format.
+    fn extensions(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Options this format accepts, as `(name, description)`.
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+}
+
+/// A format as listed by `rusty formats` and `GET /api/formats`.
+#[derive(Debug, Serialize)]
+pub struct FormatInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub export: bool,
+    pub import: bool,
+    pub options: Vec<(&'static str, &'static str)>,
+}
+
+/// Exporters and importers keyed by format name.
+#[derive(Default)]
+pub struct Registry {
+    exporters: BTreeMap<&'static str, Box<dyn Exporter>>,
+    importers: BTreeMap<&'static str, Box<dyn Importer>>,
+}
+
+impl Registry {
+    /// A registry with every built-in format.
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+
+        registry.register_exporter(dotenv::Dotenv);
+        registry.register_importer(dotenv::Dotenv);
+        registry.register_exporter(json::Json);
+        registry.register_importer(json::Json);
+        registry.register_exporter(yaml::Yaml);
+        registry.register_importer(yaml::Yaml);
+        registry.register_exporter(toml::Toml);
+        registry.register_importer(toml::Toml);
+        registry.register_exporter(docker::Docker);
+        registry.register_importer(docker::Docker);
+        registry.register_exporter(k8s::Kubernetes);
+        registry.register_exporter(shell::ShellExport::BASH);
+        registry.register_exporter(shell::ShellExport::ZSH);
+        registry.register_exporter(shell::ShellExport::FISH);
+        registry.register_exporter(shell::ShellExport::POWERSHELL);
+        registry.register_exporter(shell::ShellExport::NUSHELL);
+        registry.register_exporter(ci::GithubEnv);
+        registry.register_exporter(ci::GithubMask);
+        registry.register_exporter(ci::GitlabDotenv);
+
+        registry
+    }
+
+    pub fn register_exporter(&mut self, exporter: impl Exporter + 'static) {
+        self.exporters.insert(exporter.name(), Box::new(exporter));
+    }
+
+    pub fn register_importer(&mut self, importer: impl Importer + 'static) {
+        self.importers.insert(importer.name(), Box::new(importer));
+    }
+
+    pub fn exporter(&self, name: &str) -> Result<&dyn Exporter> {
+        self.exporters
+            .get(name)
+            .map(|exporter| exporter.as_ref())
+            .ok_or_else(|| unknown_format(name, self.exporters.keys()))
+    }
+
+    /// Exports `env` as `format`, rejecting options the format doesn't know.
+    pub fn export(&self, format: &str, env: &Environment, ctx: &ExportContext) -> Result<String> {
+        self.check_export_options(format, &ctx.options)?;
+        self.exporter(format)?.export(env, ctx)
+    }
+
+    /// Fails unless `format` can be exported with `options`.
+    pub fn check_export_options(
+        &self,
+        format: &str,
+        options: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        check_options(format, self.exporter(format)?.options(), options)
+    }
+
+    pub fn importer(&self, name: &str) -> Result<&dyn Importer> {
+        self.importers
+            .get(name)
+            .map(|importer| importer.as_ref())
+            .ok_or_else(|| unknown_format(name, self.importers.keys()))
+    }
+
+    /// Parses `input` as `format`, rejecting options the format doesn't know.
+    pub fn import(&self, format: &str, input: &str, ctx: &ImportContext) -> Result<Variables> {
+        let importer = self.importer(format)?;
+        check_options(format, importer.options(), &ctx.options)?;
+        importer.import(input, ctx)
+    }
+
+    /// The importer whose extensions match `path`, if any.
+    pub fn importer_for_path(&self, path: &Path) -> Option<&dyn Importer> {
+        let extension = path.extension()?.to_str()?;
+        self.importers
+            .values()
+            .find(|importer| importer.extensions().contains(&extension))
+            .map(|importer| importer.as_ref())
+    }
+
+    pub fn formats(&self) -> Vec<FormatInfo> {
+        let mut names: Vec<&'static str> = self
+            .exporters
+            .keys()
+            .chain(self.importers.keys())
+            .copied()
+            .collect();
+        names.sort();
+        names.dedup();
+
+        names
+            .into_iter()
+            .map(|name| {
+                let exporter = self.exporters.get(name);
+                let importer = self.importers.get(name);
+                FormatInfo {
+                    name,
+                    description: exporter
+                        .map(|e| e.description())
+                        .or(importer.map(|i| i.description()))
+                        .unwrap_or_default(),
+                    export: exporter.is_some(),
+                    import: importer.is_some(),
+                    options: {
+                        // Import and export describe a shared option differently;
+                        // export options go in last, so their description wins
+                        let options: BTreeMap<_, _> = importer
+                            .map(|i| i.options())
+                            .into_iter()
+                            .chain(exporter.map(|e| e.options()))
+                            .flatten()
+                            .copied()
+                            .collect();
+                        options.into_iter().collect()
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+fn unknown_format<'a>(name: &str, valid: impl Iterator<Item = &'a &'static str>) -> AppError {
+    let valid: Vec<&str> = valid.copied().collect();
+    AppError::InvalidInput(format!(
+        "Unknown format: {} (valid formats: {})",
+        name,
+        valid.join(", ")
+    ))
+}
+
+fn check_options(
+    format: &str,
+    known: &[(&'static str, &'static str)],
+    options: &BTreeMap<String, String>,
+) -> Result<()> {
+    match options
+        .keys()
+        .find(|name| !known.iter().any(|(known, _)| known == name))
+    {
+        Some(unknown) => {
+            let valid: Vec<&str> = known.iter().map(|(name, _)| *name).collect();
+            Err(AppError::InvalidInput(format!(
+                "Unknown option for {}: {} (valid options: {})",
+                format,
+                unknown,
+                if valid.is_empty() {
+                    "none".to_string()
+                } else {
+                    valid.join(", ")
+                }
+            )))
+        }
+        None => Ok(()),
+    }
+}
+
+/// The built-in registry, shared by the CLI and the API.
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::builtin)
+}
+
+// Every exporter emits keys in sorted order so output is stable between runs.
+fn sorted(env: &Environment) -> Vec<(&str, &EnvVariable)> {
+    let mut vars: Vec<(&str, &EnvVariable)> =
+        env.iter().map(|(key, var)| (key.as_str(), var)).collect();
+    vars.sort_by(|a, b| a.0.cmp(b.0));
+    vars
+}
+
+/// Separator nested documents are flattened with unless `separator=` says
+/// otherwise, matching the .NET configuration convention.
+const DEFAULT_SEPARATOR: &str = "__";
+
+/// A variable tree for formats with native nesting, where `APP__DB__HOST`
+/// becomes `APP -> DB -> HOST`.
+enum Tree<'a> {
+    Value(&'a EnvVariable),
+    Table(BTreeMap<&'a str, Tree<'a>>),
+}
+
+/// Splits every key on `separator` into a tree. A key that is both a value
+/// and a table (`APP__DB` and `APP__DB__HOST`) can't be represented.
+fn nest<'a>(env: &'a Environment, separator: &str) -> Result<BTreeMap<&'a str, Tree<'a>>> {
+    if separator.is_empty() {
+        return Err(AppError::InvalidInput(
+            "separator must not be empty".to_string(),
+        ));
+    }
+
+    let mut root = BTreeMap::new();
+    for (key, var) in sorted(env) {
+        let conflict = || {
+            AppError::InvalidInput(format!(
+                "{}: conflicts with another key when nested on {}",
+                key, separator
+            ))
+        };
+        let mut parts: Vec<&str> = key.split(separator).collect();
+        if parts.iter().any(|part| part.is_empty()) {
+            return Err(AppError::InvalidInput(format!(
+                "{}: empty segment when nested on {}",
+                key, separator
+            )));
+        }
+
+        let leaf = parts.pop().unwrap_or_default();
+        let mut table = &mut root;
+        for part in parts {
+            table = match table
+                .entry(part)
+                .or_insert_with(|| Tree::Table(BTreeMap::new()))
+            {
+                Tree::Table(children) => children,
+                Tree::Value(_) => return Err(conflict()),
+            };
+        }
+        if table.insert(leaf, Tree::Value(var)).is_some() {
+            return Err(conflict());
+        }
+    }
+    Ok(root)
+}
+
+/// The flat key for `key` inside the table at `prefix`.
+fn flat_key(prefix: &str, key: &str, separator: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}{}{}", prefix, separator, key)
+    }
+}
+
+/// Characters that never need quoting in dotenv, YAML keys or shell words.
+fn is_plain(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./,+@%".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const VALUES: &[&str] = &[
+        "plain",
+        "",
+        "with space",
+        " padded ",
+        "hash # comment",
+        "double \"quote\"",
+        "single 'quote'",
+        "both ' and \"",
+        "colon: value",
+        "multi\nline\r\nvalue",
+        "tab\tseparated",
+        "$HOME and ${PATH} and `cmd`",
+        "back\\slash",
+        "true",
+        "8080",
+        "- list item",
+        "unicode ✓ \u{1b}[0m",
+    ];
+
+    fn environment() -> HashMap<String, EnvVariable> {
+        VALUES
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                (
+                    format!("KEY_{:02}", i),
+                    EnvVariable::new(value.to_string(), false),
+                )
+            })
+            .collect()
+    }
+
+    fn expected() -> Variables {
+        let mut vars: Variables = environment()
+            .into_iter()
+            .map(|(key, var)| (key, var.value))
+            .collect();
+        vars.sort();
+        vars
+    }
+
+    fn round_trip(format: &str) -> Variables {
+        let output = registry()
+            .export(format, &environment(), &ExportContext::default())
+            .unwrap();
+        registry()
+            .import(format, &output, &ImportContext::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn lists_shared_options_once_with_the_export_description() {
+        struct Format(&'static [(&'static str, &'static str)]);
+        impl Exporter for Format {
+            fn name(&self) -> &'static str {
+                "test"
+            }
+            fn description(&self) -> &'static str {
+                "Test"
+            }
+            fn options(&self) -> &'static [(&'static str, &'static str)] {
+                self.0
+            }
+            fn export(&self, _: &Environment, _: &ExportContext) -> Result<String> {
+                Ok(String::new())
+            }
+        }
+        impl Importer for Format {
+            fn name(&self) -> &'static str {
+                "test"
+            }
+            fn description(&self) -> &'static str {
+                "Test"
+            }
+            fn options(&self) -> &'static [(&'static str, &'static str)] {
+                self.0
+            }
+            fn import(&self, _: &str, _: &ImportContext) -> Result<Variables> {
+                Ok(Variables::new())
+            }
+        }
+
+        let mut registry = Registry::default();
+        registry.register_exporter(Format(&[("shared", "export"), ("out", "")]));
+        registry.register_importer(Format(&[("in", ""), ("shared", "import")]));
+        assert_eq!(
+            registry.formats()[0].options,
+            [("in", ""), ("out", ""), ("shared", "export")]
+        );
+    }
+
+    #[test]
+    fn dotenv_round_trips() {
+        assert_eq!(round_trip("dotenv"), expected());
+    }
+
+    #[test]
+    fn json_round_trips() {
+        assert_eq!(round_trip("json"), expected());
+    }
+
+    #[test]
+    fn yaml_round_trips() {
+        assert_eq!(round_trip("yaml"), expected());
+    }
+
+    #[test]
+    fn docker_round_trips() {
+        assert_eq!(round_trip("docker"), expected());
+    }
+
+    #[test]
+    fn toml_round_trips() {
+        assert_eq!(round_trip("toml"), expected());
+    }
+
+    fn nested_environment() -> Environment {
+        [
+            ("APP__DB__HOST", "db.internal"),
+            ("APP__DB__PORT", "5432"),
+            ("APP__NAME", "web"),
+            ("LOG_LEVEL", "info"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), EnvVariable::new(v.to_string(), false)))
+        .collect()
+    }
+
+    #[test]
+    fn nested_formats_round_trip() {
+        let ctx = ExportContext {
+            options: [("separator".to_string(), "__".to_string())].into(),
+            ..Default::default()
+        };
+        let mut expected: Variables = nested_environment()
+            .into_iter()
+            .map(|(key, var)| (key, var.value))
+            .collect();
+        expected.sort();
+
+        for format in ["json", "yaml", "toml"] {
+            let output = registry()
+                .export(format, &nested_environment(), &ctx)
+                .unwrap();
+            assert!(!output.contains("APP__"), "{}: {}", format, output);
+
+            let mut vars = registry()
+                .import(format, &output, &ImportContext::default())
+                .unwrap();
+            vars.sort();
+            assert_eq!(vars, expected, "{}", format);
+        }
+    }
+
+    #[test]
+    fn nesting_rejects_values_that_are_also_tables() {
+        let mut env = nested_environment();
+        env.insert(
+            "APP__DB".to_string(),
+            EnvVariable::new("x".to_string(), false),
+        );
+        assert!(nest(&env, "__").is_err());
+    }
+
+    #[test]
+    fn builtin_formats_support_both_directions() {
+        let formats = registry().formats();
+        for name in ["docker", "dotenv", "json", "toml", "yaml"] {
+            let format = formats.iter().find(|f| f.name == name).unwrap();
+            assert!(format.export && format.import, "{}", name);
+        }
+    }
+
+    #[test]
+    fn unknown_format_lists_valid_ones() {
+        let err = registry().exporter("nope").err().unwrap().to_string();
+        assert!(err.starts_with("Invalid input: Unknown format: nope (valid formats: "));
+        assert!(err.contains("dotenv") && err.contains("json"));
+    }
+}
@@ -0,0 +1,187 @@
+use super::{ExportContext, Exporter, sorted};
+use crate::error::{AppError, Result};
+use crate::models::Environment;
+use crate::shell::{is_valid_name, quote_fish, quote_posix};
+
+#[derive(Clone, Copy)]
+enum Dialect {
+    Posix,
+    Fish,
+    PowerShell,
+    Nushell,
+}
+
+/// Statements that set variables in the current shell, safe to `eval`.
+pub struct ShellExport {
+    name: &'static str,
+    description: &'static str,
+    dialect: Dialect,
+}
+
+impl ShellExport {
+    pub const BASH: Self = Self {
+        name: "bash",
+        description: "export KEY='value' for bash and POSIX sh",
+        dialect: Dialect::Posix,
+    };
+    pub const ZSH: Self = Self {
+        name: "zsh",
+        description: "export KEY='value' for zsh",
+        dialect: Dialect::Posix,
+    };
+    pub const FISH: Self = Self {
+        name: "fish",
+        description: "set -gx KEY 'value' for fish",
+        dialect: Dialect::Fish,
+    };
+    pub const POWERSHELL: Self = Self {
+        name: "powershell",
+        description: "$env:KEY = 'value' for PowerShell",
+        dialect: Dialect::PowerShell,
+    };
+    pub const NUSHELL: Self = Self {
+        name: "nushell",
+        description: "$env.KEY = \"value\" for nushell",
+        dialect: Dialect::Nushell,
+    };
+}
+
+impl Exporter for ShellExport {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn export(&self, env: &Environment, _ctx: &ExportContext) -> Result<String> {
+        sorted(env)
+            .into_iter()
+            .map(|(key, var)| {
+                // Keys are emitted unquoted, so anything else could inject code
+                if !is_valid_name(key) {
+                    return Err(AppError::InvalidInput(format!(
+                        "{} is not a valid {} variable name",
+                        key, self.name
+                    )));
+                }
+                let value = &var.value;
+                Ok(match self.dialect {
+                    Dialect::Posix => format!("export {}={}", key, quote_posix(value)),
+                    Dialect::Fish => format!("set -gx {} {}", key, quote_fish(value)),
+                    Dialect::PowerShell => format!("$env:{} = {}", key, quote_powershell(value)),
+                    Dialect::Nushell => format!("$env.{} = {}", key, quote_nushell(value)),
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|lines| lines.join("\n"))
+    }
+}
+
+/// Single-quotes `value` for PowerShell, which treats the typographic
+/// single quotes as quote characters too; each is escaped by doubling.
+fn quote_powershell(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Double-quotes `value` for nushell using its backslash escapes.
+fn quote_nushell(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str(r"\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str(r"\n"),
+            '\r' => quoted.push_str(r"\r"),
+            '\t' => quoted.push_str(r"\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvVariable;
+
+    fn environment(value: &str) -> Environment {
+        [(
+            "KEY".to_string(),
+            EnvVariable::new(value.to_string(), false),
+        )]
+        .into_iter()
+        .collect()
+    }
+
+    fn export(exporter: &ShellExport, value: &str) -> String {
+        exporter
+            .export(&environment(value), &ExportContext::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn quotes_per_shell() {
+        let value = "it's $HOME\\n";
+        assert_eq!(
+            export(&ShellExport::BASH, value),
+            r"export KEY='it'\''s $HOME\n'"
+        );
+        assert_eq!(
+            export(&ShellExport::FISH, value),
+            r"set -gx KEY 'it\'s $HOME\\n'"
+        );
+        assert_eq!(
+            export(&ShellExport::POWERSHELL, "it's ‘x’"),
+            "$env:KEY = 'it''s ‘‘x’’'"
+        );
+        assert_eq!(
+            export(&ShellExport::NUSHELL, "say \"hi\"\n"),
+            r#"$env.KEY = "say \"hi\"\n""#
+        );
+    }
+
+    #[test]
+    fn rejects_keys_that_are_not_names() {
+        let env: Environment = [(
+            "A;rm -rf /".to_string(),
+            EnvVariable::new("x".to_string(), false),
+        )]
+        .into_iter()
+        .collect();
+        assert!(
+            ShellExport::BASH
+                .export(&env, &ExportContext::default())
+                .is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn posix_output_evaluates_to_original_values() {
+        let value = "a 'b' \"c\" $d `e` \\f\ng #h";
+        let script = format!(
+            "{}\nprintf '%s' \"$KEY\"",
+            export(&ShellExport::BASH, value)
+        );
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), value);
+    }
+}
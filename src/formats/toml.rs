@@ -0,0 +1,146 @@
+use super::{
+    ExportContext, Exporter, ImportContext, Importer, Tree, Variables, flat_key, nest, sorted,
+};
+use crate::error::{AppError, Result};
+use crate::models::Environment;
+use toml::{Table, Value};
+
+/// A TOML document of string values, with `separator=` nesting keys into
+/// tables.
+pub struct Toml;
+
+impl Exporter for Toml {
+    fn name(&self) -> &'static str {
+        "toml"
+    }
+
+    fn description(&self) -> &'static str {
+        "TOML document, flat or nested"
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[("separator", "Nest keys split on this separator, e.g. __")]
+    }
+
+    fn export(&self, env: &Environment, ctx: &ExportContext) -> Result<String> {
+        let table: Table = match ctx.option("separator") {
+            Some(separator) => nest(env, separator)?
+                .into_iter()
+                .map(|(key, tree)| (key.to_string(), to_value(tree)))
+                .collect(),
+            None => sorted(env)
+                .into_iter()
+                .map(|(key, var)| (key.to_string(), Value::String(var.value.clone())))
+                .collect(),
+        };
+        toml::to_string(&table)
+            .map(|output| output.trim_end().to_string())
+            .map_err(|e| AppError::InvalidInput(format!("cannot render TOML: {}", e)))
+    }
+}
+
+impl Importer for Toml {
+    fn name(&self) -> &'static str {
+        "toml"
+    }
+
+    fn description(&self) -> &'static str {
+        "TOML document, flat or nested"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["toml"]
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[(
+            "separator",
+            "Join nested keys with this separator (default: __)",
+        )]
+    }
+
+    fn import(&self, input: &str, ctx: &ImportContext) -> Result<Variables> {
+        parse(input, ctx.separator())
+    }
+}
+
+fn to_value(tree: Tree) -> Value {
+    match tree {
+        Tree::Value(var) => Value::String(var.value.clone()),
+        Tree::Table(children) => Value::Table(
+            children
+                .into_iter()
+                .map(|(key, tree)| (key.to_string(), to_value(tree)))
+                .collect(),
+        ),
+    }
+}
+
+/// Parses a TOML document; numbers, booleans and datetimes become strings,
+/// and tables and arrays are flattened into `PARENT{separator}CHILD`.
+fn parse(input: &str, separator: &str) -> Result<Variables> {
+    let table: Table = toml::from_str(input)
+        .map_err(|e| AppError::InvalidInput(format!("invalid TOML: {}", e)))?;
+
+    let mut vars = Vec::new();
+    flatten(String::new(), &Value::Table(table), separator, &mut vars);
+    Ok(vars)
+}
+
+fn flatten(prefix: String, value: &Value, separator: &str, vars: &mut Variables) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                flatten(flat_key(&prefix, key, separator), value, separator, vars);
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(
+                    flat_key(&prefix, &i.to_string(), separator),
+                    value,
+                    separator,
+                    vars,
+                );
+            }
+        }
+        Value::String(s) => vars.push((prefix, s.clone())),
+        Value::Integer(i) => vars.push((prefix, i.to_string())),
+        Value::Float(f) => vars.push((prefix, f.to_string())),
+        Value::Boolean(b) => vars.push((prefix, b.to_string())),
+        Value::Datetime(dt) => vars.push((prefix, dt.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvVariable;
+
+    #[test]
+    fn nests_into_tables() {
+        let env: Environment = [("APP__DB__HOST", "db"), ("APP__NAME", "web")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), EnvVariable::new(v.to_string(), false)))
+            .collect();
+        let ctx = ExportContext {
+            options: [("separator".to_string(), "__".to_string())].into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            Toml.export(&env, &ctx).unwrap(),
+            "[APP]\nNAME = \"web\"\n\n[APP.DB]\nHOST = \"db\""
+        );
+    }
+
+    #[test]
+    fn scalars_become_strings() {
+        assert_eq!(
+            parse("[server]\nport = 8080\ntls = true\n", ".").unwrap(),
+            vec![
+                ("server.port".to_string(), "8080".to_string()),
+                ("server.tls".to_string(), "true".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use super::{
+    ExportContext, Exporter, ImportContext, Importer, Tree, Variables, flat_key, is_plain, nest,
+    sorted,
+};
+use crate::error::{AppError, Result};
+use crate::models::Environment;
+use yaml_rust2::{Yaml as Node, YamlLoader};
+
+/// A YAML mapping of double-quoted strings, nested when `separator=` is
+/// given.
+pub struct Yaml;
+
+impl Exporter for Yaml {
+    fn name(&self) -> &'static str {
+        "yaml"
+    }
+
+    fn description(&self) -> &'static str {
+        "YAML mapping, flat or nested"
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[("separator", "Nest keys split on this separator, e.g. __")]
+    }
+
+    fn export(&self, env: &Environment, ctx: &ExportContext) -> Result<String> {
+        let mut lines = Vec::new();
+        match ctx.option("separator") {
+            Some(separator) => render(&nest(env, separator)?, 0, &mut lines),
+            None => {
+                for (key, var) in sorted(env) {
+                    lines.push(format!("{}: {}", key_of(key), quote(&var.value)));
+                }
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+impl Importer for Yaml {
+    fn name(&self) -> &'static str {
+        "yaml"
+    }
+
+    fn description(&self) -> &'static str {
+        "YAML mapping, flat or nested"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["yaml", "yml"]
+    }
+
+    fn options(&self) -> &'static [(&'static str, &'static str)] {
+        &[(
+            "separator",
+            "Join nested keys with this separator (default: __)",
+        )]
+    }
+
+    fn import(&self, input: &str, ctx: &ImportContext) -> Result<Variables> {
+        parse(input, ctx.separator())
+    }
+}
+
+fn render(table: &std::collections::BTreeMap<&str, Tree>, depth: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    for (key, tree) in table {
+        match tree {
+            Tree::Value(var) => {
+                lines.push(format!("{}{}: {}", indent, key_of(key), quote(&var.value)))
+            }
+            Tree::Table(children) => {
+                lines.push(format!("{}{}:", indent, key_of(key)));
+                render(children, depth + 1, lines);
+            }
+        }
+    }
+}
+
+/// Keys stay plain unless YAML would read them as something other than a
+/// string (`on`, `null`, ...) or they contain special characters.
+pub(super) fn key_of(key: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "y", "n", "yes", "no", "on", "off", "true", "false", "null", "~",
+    ];
+
+    let reserved = RESERVED.contains(&key.to_ascii_lowercase().as_str());
+    let starts_plain = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    if is_plain(key) && starts_plain && !reserved {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+/// Values are always double-quoted so numbers and booleans stay strings.
+pub(super) fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str(r"\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str(r"\n"),
+            '\r' => quoted.push_str(r"\r"),
+            '\t' => quoted.push_str(r"\t"),
+            c if c.is_control() || matches!(c, '\u{2028}' | '\u{2029}' | '\u{feff}') => {
+                quoted.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parses a YAML mapping; scalars of any type become strings, and nested
+/// mappings and sequences are flattened into `PARENT{separator}CHILD`.
+fn parse(input: &str, separator: &str) -> Result<Variables> {
+    let docs = YamlLoader::load_from_str(input)
+        .map_err(|e| AppError::InvalidInput(format!("invalid YAML: {}", e)))?;
+    let mut vars = Vec::new();
+    match docs.first() {
+        Some(node @ Node::Hash(_)) => flatten(String::new(), node, separator, &mut vars)?,
+        None => {}
+        Some(_) => {
+            return Err(AppError::InvalidInput(
+                "expected a YAML mapping".to_string(),
+            ));
+        }
+    }
+    Ok(vars)
+}
+
+fn flatten(prefix: String, node: &Node, separator: &str, vars: &mut Variables) -> Result<()> {
+    match node {
+        Node::Hash(hash) => {
+            for (key, value) in hash {
+                let key = scalar(key)
+                    .ok_or_else(|| AppError::InvalidInput("keys must be scalars".to_string()))?;
+                flatten(flat_key(&prefix, &key, separator), value, separator, vars)?;
+            }
+        }
+        Node::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(
+                    flat_key(&prefix, &i.to_string(), separator),
+                    value,
+                    separator,
+                    vars,
+                )?;
+            }
+        }
+        _ => {
+            let value = scalar(node).ok_or_else(|| {
+                AppError::InvalidInput(format!("{}: unsupported YAML value", prefix))
+            })?;
+            vars.push((prefix, value));
+        }
+    }
+    Ok(())
+}
+
+fn scalar(value: &Node) -> Option<String> {
+    match value {
+        Node::String(s) | Node::Real(s) => Some(s.clone()),
+        Node::Integer(i) => Some(i.to_string()),
+        Node::Boolean(b) => Some(b.to_string()),
+        Node::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvVariable;
+
+    #[test]
+    fn reserved_keys_are_quoted() {
+        let env: Environment = ["on", "NULL", "1KEY", "OK"]
+            .iter()
+            .map(|key| (key.to_string(), EnvVariable::new("x".to_string(), false)))
+            .collect();
+        assert_eq!(
+            Yaml.export(&env, &ExportContext::default()).unwrap(),
+            "\"1KEY\": \"x\"\n\"NULL\": \"x\"\nOK: \"x\"\n\"on\": \"x\""
+        );
+    }
+
+    #[test]
+    fn scalars_become_strings() {
+        assert_eq!(
+            parse("FLAG: true\nPORT: 8080\n", "__").unwrap(),
+            vec![
+                ("FLAG".to_string(), "true".to_string()),
+                ("PORT".to_string(), "8080".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_mappings_are_flattened() {
+        assert_eq!(
+            parse("APP:\n  DB:\n    HOST: db\n  HOSTS: [a, b]\n", "__").unwrap(),
+            vec![
+                ("APP__DB__HOST".to_string(), "db".to_string()),
+                ("APP__HOSTS__0".to_string(), "a".to_string()),
+                ("APP__HOSTS__1".to_string(), "b".to_string()),
+            ]
+        );
+    }
+}
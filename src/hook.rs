@@ -0,0 +1,306 @@
+use crate::binding::ProjectBinding;
+use crate::error::Result;
+use crate::models::Environment;
+use crate::shell::{self, Shell};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Shell variable holding what the hook loaded, so it can be unloaded later.
+pub const STATE_VAR: &str = "RUSTY_HOOK_STATE";
+
+/// What the hook last loaded into the shell.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HookState {
+    pub binding: PathBuf,
+    pub project: String,
+    pub environment: String,
+    pub allowed: bool,
+    /// Values the loaded keys had before loading; `None` if they were unset.
+    pub previous: BTreeMap<String, Option<String>>,
+    /// Why loading failed, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HookState {
+    pub fn from_env() -> Option<Self> {
+        let raw = std::env::var(STATE_VAR).ok()?;
+        serde_json::from_str(&raw).ok()
+    }
+
+    /// Whether this state already reflects `binding` loaded as `environment`.
+    pub fn matches(&self, binding: &ProjectBinding, environment: &str, allowed: bool) -> bool {
+        self.binding == binding.path
+            && self.project == binding.project
+            && self.environment == environment
+            && self.allowed == allowed
+    }
+
+    /// Shell code restoring every key to its value before loading.
+    pub fn unload(&self, shell: Shell) -> String {
+        let mut out = String::new();
+        for (key, previous) in &self.previous {
+            let line = match previous {
+                Some(value) => shell.export(key, value),
+                None => shell.unset(key),
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str(&shell.unset(STATE_VAR));
+        out.push('\n');
+        out
+    }
+}
+
+/// Shell code exporting `environment` and recording a state to undo it.
+///
+/// `unloaded` is the state being replaced, whose previous values are what
+/// the shell will hold once its unload code has run.
+pub fn load(
+    shell: Shell,
+    binding: &ProjectBinding,
+    environment_name: &str,
+    environment: &Environment,
+    unloaded: Option<&HookState>,
+) -> Result<String> {
+    let mut out = String::new();
+    let mut previous = BTreeMap::new();
+
+    let mut keys: Vec<&String> = environment.keys().collect();
+    keys.sort();
+
+    for key in keys {
+        if !shell::is_valid_name(key) || key == STATE_VAR {
+            eprintln!("rusty: skipping {}: not a valid variable name", key);
+            continue;
+        }
+
+        let current = match unloaded.and_then(|state| state.previous.get(key)) {
+            Some(value) => value.clone(),
+            None => std::env::var(key).ok(),
+        };
+        previous.insert(key.clone(), current);
+
+        out.push_str(&shell.export(key, &environment[key].value));
+        out.push('\n');
+    }
+
+    out.push_str(&record(
+        shell,
+        &HookState {
+            binding: binding.path.clone(),
+            project: binding.project.clone(),
+            environment: environment_name.to_string(),
+            allowed: true,
+            previous,
+            error: None,
+        },
+    )?);
+    Ok(out)
+}
+
+/// Shell code recording that `binding` was seen but not loaded, so the
+/// warning is not repeated on every prompt.
+pub fn blocked(shell: Shell, binding: &ProjectBinding, environment_name: &str) -> Result<String> {
+    record(
+        shell,
+        &HookState {
+            binding: binding.path.clone(),
+            project: binding.project.clone(),
+            environment: environment_name.to_string(),
+            allowed: false,
+            previous: BTreeMap::new(),
+            error: None,
+        },
+    )
+}
+
+/// Shell code recording that loading `binding` failed with `error`, so the
+/// error is reported once rather than on every prompt.
+pub fn failed(
+    shell: Shell,
+    binding: &ProjectBinding,
+    environment_name: &str,
+    error: &str,
+) -> Result<String> {
+    record(
+        shell,
+        &HookState {
+            binding: binding.path.clone(),
+            project: binding.project.clone(),
+            environment: environment_name.to_string(),
+            allowed: true,
+            previous: BTreeMap::new(),
+            error: Some(error.to_string()),
+        },
+    )
+}
+
+fn record(shell: Shell, state: &HookState) -> Result<String> {
+    let json = serde_json::to_string(state)?;
+    Ok(format!("{}\n", shell.export(STATE_VAR, &json)))
+}
+
+/// Bindings the user has approved, keyed by path and pinned to the file's
+/// contents so an edited `.rusty.toml` must be approved again.
+pub struct AllowList {
+    path: PathBuf,
+}
+
+impl AllowList {
+    /// The allow list under `$XDG_DATA_HOME/rusty` (or `~/.local/share/rusty`).
+    pub fn open() -> Self {
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        Self {
+            path: data_home.join("rusty").join("allow"),
+        }
+    }
+
+    pub fn is_allowed(&self, binding: &Path) -> Result<bool> {
+        let entry = Self::entry(binding)?;
+        Ok(self.entries()?.contains(&entry))
+    }
+
+    pub fn allow(&self, binding: &Path) -> Result<()> {
+        let entry = Self::entry(binding)?;
+        let canonical = binding.canonicalize()?;
+        let mut entries: Vec<String> = self
+            .entries()?
+            .into_iter()
+            .filter(|line| !line.ends_with(&format!(" {}", canonical.display())))
+            .collect();
+        entries.push(entry);
+        self.write(&entries)
+    }
+
+    pub fn deny(&self, binding: &Path) -> Result<()> {
+        let canonical = binding.canonicalize()?;
+        let entries: Vec<String> = self
+            .entries()?
+            .into_iter()
+            .filter(|line| !line.ends_with(&format!(" {}", canonical.display())))
+            .collect();
+        self.write(&entries)
+    }
+
+    fn entries(&self) -> Result<Vec<String>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        Ok(fs::read_to_string(&self.path)?
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn write(&self, entries: &[String]) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut contents = entries.join("\n");
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+        fs::write(&self.path, contents)?;
+        Ok(())
+    }
+
+    /// `<sha256 of contents> <canonical path>`
+    fn entry(binding: &Path) -> Result<String> {
+        let contents = fs::read(binding)?;
+        let digest = Sha256::digest(&contents);
+        Ok(format!(
+            "{:x} {}",
+            digest,
+            binding.canonicalize()?.display()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvVariable;
+
+    fn binding() -> ProjectBinding {
+        ProjectBinding {
+            project: "app".to_string(),
+            environment: None,
+            path: PathBuf::from("/src/app/.rusty.toml"),
+        }
+    }
+
+    /// The state recorded by bash hook output, as the next prompt sees it.
+    fn recorded(out: &str) -> HookState {
+        let prefix = format!("export {}=", STATE_VAR);
+        let line = out.lines().find(|line| line.starts_with(&prefix)).unwrap();
+        let quoted = line[prefix.len()..].trim_end_matches(';');
+        serde_json::from_str(&quoted[1..quoted.len() - 1].replace(r"'\''", "'")).unwrap()
+    }
+
+    #[test]
+    fn load_records_previous_values_and_unload_restores_them() {
+        let environment: Environment = [(
+            "RUSTY_TEST_UNSET_KEY".to_string(),
+            EnvVariable::new("it's".to_string(), false),
+        )]
+        .into();
+        let out = load(Shell::Bash, &binding(), "dev", &environment, None).unwrap();
+        assert!(out.starts_with("export RUSTY_TEST_UNSET_KEY='it'\\''s';\n"));
+
+        let state = recorded(&out);
+        assert!(state.matches(&binding(), "dev", true));
+        assert!(!state.matches(&binding(), "prod", true));
+        assert_eq!(state.previous["RUSTY_TEST_UNSET_KEY"], None);
+        assert_eq!(
+            state.unload(Shell::Bash),
+            format!("unset RUSTY_TEST_UNSET_KEY;\nunset {};\n", STATE_VAR)
+        );
+
+        // Switching environments keeps the values from before the first load
+        let mut replaced = state.clone();
+        replaced.previous.insert(
+            "RUSTY_TEST_UNSET_KEY".to_string(),
+            Some("original".to_string()),
+        );
+        let out = load(
+            Shell::Bash,
+            &binding(),
+            "prod",
+            &environment,
+            Some(&replaced),
+        )
+        .unwrap();
+        assert_eq!(
+            recorded(&out).previous["RUSTY_TEST_UNSET_KEY"],
+            Some("original".to_string())
+        );
+    }
+
+    #[test]
+    fn blocked_and_failed_states_load_nothing() {
+        let state = recorded(&blocked(Shell::Bash, &binding(), "dev").unwrap());
+        assert!(!state.allowed && state.previous.is_empty());
+        assert!(state.matches(&binding(), "dev", false));
+        assert!(!state.matches(&binding(), "dev", true));
+
+        let out = failed(Shell::Bash, &binding(), "dev", "Environment not found: dev").unwrap();
+        let state = recorded(&out);
+        assert_eq!(state.error.as_deref(), Some("Environment not found: dev"));
+        assert!(state.matches(&binding(), "dev", true));
+        assert_eq!(state.unload(Shell::Bash), format!("unset {};\n", STATE_VAR));
+    }
+}
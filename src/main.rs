@@ -1,37 +1,187 @@
+mod agent;
+mod binding;
 mod cli;
 mod config;
 mod db;
+mod diff;
 mod error;
+mod formats;
+mod hook;
+mod manifest;
 mod models;
 mod routes;
+mod runner;
+mod shell;
+mod webhook;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::PathBuf;
 
 use anyhow::Context;
+use binding::{ProjectBinding, Scope};
 use clap::Parser;
-use cli::{Cli, Commands, EnvCommands, ProjectCommands};
-use config::AppConfig;
+use cli::{
+    Cli, Commands, ConfigCommands, EnvCommands, ProjectCommands, SchemaCommands, WebhookCommands,
+};
+use config::{AppConfig, CliOverride};
 use db::JsonStore;
 
-use crate::models::{EnvVariable, Project};
+use crate::models::{
+    ChangeEvent, ChangeKind, EnvVariable, EnvironmentPatch, EnvironmentSettings, ImportOptions,
+    Project, PromoteOptions, SchemaRule, VariableFields, VariablePatch, Webhook,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let config = AppConfig::load(cli.config).context("Failed to load configuration")?;
+    let config = AppConfig::load(cli.config, cli_overrides(&cli.command))
+        .context("Failed to load configuration")?;
 
     match cli.command {
-        Commands::Serve => serve(config).await?,
-        Commands::Project(cmd) => handle_project_command(cmd, &config).await?,
-        Commands::Env(cmd) => handle_env_command(cmd, &config).await?,
+        Commands::Serve { .. } => serve(config).await?,
+        Commands::Project(cmd) => {
+            let store = JsonStore::new(config.database.path.clone())?;
+            with_webhooks(&store, handle_project_command(cmd, &store)).await?
+        }
+        Commands::Env(cmd) => {
+            let store = JsonStore::new(config.database.path.clone())?;
+            with_webhooks(&store, handle_env_command(cmd, &store, &config)).await?
+        }
+        Commands::Schema(cmd) => {
+            let store = JsonStore::new(config.database.path.clone())?;
+            with_webhooks(&store, handle_schema_command(cmd, &store, &config)).await?
+        }
+        Commands::Webhook(cmd) => {
+            let store = JsonStore::new(config.database.path.clone())?;
+            with_webhooks(&store, handle_webhook_command(cmd, &store, &config)).await?
+        }
+        Commands::Config(cmd) => handle_config_command(cmd, &config)?,
+        Commands::Run {
+            project,
+            env,
+            no_override,
+            redact,
+            command,
+            ..
+        } => {
+            let code = run_command(project, env, !no_override, redact, command, &config).await?;
+            std::process::exit(code);
+        }
+        Commands::Hook { shell } => {
+            let exe = std::env::current_exe().context("Failed to locate the rusty binary")?;
+            print!("{}", shell.hook(&exe));
+        }
+        Commands::Plan { file } => {
+            let store = JsonStore::new(config.database.path.clone())?;
+            let actions = manifest::plan(&load_manifest(&file)?, &store).await?;
+            if !print_plan(&actions) {
+                // Like `terraform plan -detailed-exitcode`: 1 is an error, 2 is drift
+                std::process::exit(2);
+            }
+        }
+        Commands::Apply { file, yes } => {
+            let store = JsonStore::new(config.database.path.clone())?;
+            with_webhooks(&store, apply_manifest(&file, yes, &store)).await?
+        }
+        Commands::Agent { file, server, once } => {
+            let input = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let agent_config = agent::AgentConfig::parse(&input)
+                .with_context(|| format!("Failed to load {}", file.display()))?;
+            let server = server
+                .or_else(|| agent_config.server.clone())
+                .unwrap_or_else(|| {
+                    // A server bound to every interface is reachable on loopback
+                    let host = match config.server.host.as_str() {
+                        "0.0.0.0" | "::" => "127.0.0.1",
+                        host => host,
+                    };
+                    format!("http://{}:{}", host, config.server.port)
+                });
+            let mut agent = agent::Agent::new(agent_config, &server)?;
+            if once {
+                agent.render_once().await?;
+            } else {
+                println!("👀 Watching {} for changes", server);
+                agent.run().await?;
+            }
+        }
+        Commands::Formats => {
+            println!("Formats:");
+            for format in formats::registry().formats() {
+                let direction = match (format.export, format.import) {
+                    (true, true) => "export, import",
+                    (true, false) => "export",
+                    (false, true) => "import",
+                    (false, false) => "",
+                };
+                println!(
+                    "  • {:<10} {} ({})",
+                    format.name, format.description, direction
+                );
+                for (name, description) in format.options {
+                    println!("      -o {}=...  {}", name, description);
+                }
+            }
+        }
+        Commands::HookEnv { shell } => print!("{}", hook_env(shell, &config).await?),
+        Commands::Allow { path } => {
+            let binding = locate_binding(path)?;
+            hook::AllowList::open().allow(&binding.path)?;
+            println!("✓ Allowed {}", binding.path.display());
+        }
+        Commands::Deny { path } => {
+            let binding = locate_binding(path)?;
+            hook::AllowList::open().deny(&binding.path)?;
+            println!("✓ Denied {}", binding.path.display());
+        }
     }
 
     Ok(())
 }
 
+/// Command-line flags that take precedence over every other configuration layer.
+fn cli_overrides(command: &Commands) -> Vec<CliOverride> {
+    let mut overrides = Vec::new();
+
+    if let Commands::Serve { host, port } = command {
+        if let Some(host) = host {
+            overrides.push(CliOverride {
+                key: "server.host",
+                flag: "--host",
+                value: host.clone(),
+            });
+        }
+        if let Some(port) = port {
+            overrides.push(CliOverride {
+                key: "server.port",
+                flag: "--port",
+                value: port.to_string(),
+            });
+        }
+    }
+
+    overrides
+}
+
+/// Runs a command that may change the store, then waits for the webhooks
+/// its changes set off. Each gets one short attempt, so an unreachable
+/// endpoint can't hold the command up.
+async fn with_webhooks(
+    store: &JsonStore,
+    command: impl Future<Output = anyhow::Result<()>>,
+) -> anyhow::Result<()> {
+    let webhooks = webhook::Dispatcher::start(store, webhook::Policy::COMMAND);
+    let result = command.await;
+    webhooks.finish().await;
+    result
+}
+
 async fn serve(config: AppConfig) -> anyhow::Result<()> {
     let store = JsonStore::new(config.database.path.clone())?;
-    let app = routes::create_router(store);
+    let webhooks = webhook::Dispatcher::start(&store, webhook::Policy::BACKGROUND);
+    let app = routes::create_router(store, config.defaults.clone());
 
     let addr = format!("{}:{}", config.server.host, config.server.port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
@@ -39,13 +189,12 @@ async fn serve(config: AppConfig) -> anyhow::Result<()> {
     println!("🚀 Server running on http://{}", addr);
 
     axum::serve(listener, app).await?;
+    webhooks.finish().await;
 
     Ok(())
 }
 
-async fn handle_project_command(cmd: ProjectCommands, config: &AppConfig) -> anyhow::Result<()> {
-    let store = JsonStore::new(config.database.path.clone())?;
-
+async fn handle_project_command(cmd: ProjectCommands, store: &JsonStore) -> anyhow::Result<()> {
     match cmd {
         ProjectCommands::Add { name, description } => {
             let project = store.create_project(name, description).await?;
@@ -77,8 +226,29 @@ async fn handle_project_command(cmd: ProjectCommands, config: &AppConfig) -> any
                 println!("Description: {}", desc);
             }
             println!("Environments: {}", project.environments.len());
-            for env_name in project.environments.keys() {
-                println!("  • {}", env_name);
+            let mut env_names: Vec<&String> = project.environments.keys().collect();
+            env_names.sort();
+            for env_name in env_names {
+                let settings = project
+                    .environment_settings
+                    .get(env_name)
+                    .cloned()
+                    .unwrap_or_default();
+                let mut details = Vec::new();
+                if let Some(parent) = settings.parent {
+                    details.push(format!("inherits {}", parent));
+                }
+                if settings.protected {
+                    details.push("protected".to_string());
+                }
+                print!("  • {}", env_name);
+                if !details.is_empty() {
+                    print!(" ({})", details.join(", "));
+                }
+                match settings.description {
+                    Some(description) => println!(" - {}", description),
+                    None => println!(),
+                }
             }
         }
         ProjectCommands::Delete { name } => {
@@ -90,43 +260,145 @@ async fn handle_project_command(cmd: ProjectCommands, config: &AppConfig) -> any
     Ok(())
 }
 
-async fn handle_env_command(cmd: EnvCommands, config: &AppConfig) -> anyhow::Result<()> {
-    let store = JsonStore::new(config.database.path.clone())?;
+/// The scope of a command that takes an optional leading project argument.
+async fn discover_scope(store: &JsonStore, config: &AppConfig) -> anyhow::Result<Scope> {
+    let projects = store
+        .list_projects()
+        .await?
+        .into_iter()
+        .map(|project| project.name)
+        .collect();
+    Ok(Scope::discover(config.defaults.environment.clone())?.with_projects(projects))
+}
+
+async fn handle_env_command(
+    cmd: EnvCommands,
+    store: &JsonStore,
+    config: &AppConfig,
+) -> anyhow::Result<()> {
+    let scope = discover_scope(store, config).await?;
 
     match cmd {
         EnvCommands::Set {
-            project,
-            key,
-            value,
+            args,
             env,
             encrypted,
+            r#type,
         } => {
-            store
-                .set_variable(&project, &env, key.clone(), value.clone(), encrypted)
+            let (project, [key, value]) = scope.split_args(args)?;
+            let env = scope.environment(env);
+            let variable = store
+                .set_variable(
+                    &project,
+                    &env,
+                    key.clone(),
+                    value.clone(),
+                    encrypted,
+                    r#type,
+                )
                 .await?;
             println!(
-                "✓ Set {}={} in {}/{}",
+                "✓ Set {}={} in {}/{}{}",
                 key,
                 if encrypted { "***" } else { &value },
                 project,
-                env
+                env,
+                if variable.value_type.is_string() {
+                    String::new()
+                } else {
+                    format!(" ({})", variable.value_type)
+                }
             );
         }
-        EnvCommands::Get { project, key, env } => {
-            let variable = store.get_variable(&project, &env, &key).await?;
+        EnvCommands::SetMany {
+            mut args,
+            env,
+            unset,
+            encrypted,
+        } => {
+            let project = match args.first() {
+                Some(first) if !first.contains('=') => Some(args.remove(0)),
+                _ => None,
+            };
+            let project = scope.project(project)?;
+            let env = scope.environment(env);
+
+            let mut patch = EnvironmentPatch::new();
+            for arg in &args {
+                let (key, value) = arg
+                    .split_once('=')
+                    .with_context(|| format!("Expected KEY=VALUE, got {}", arg))?;
+                let fields = VariableFields {
+                    value: Some(value.to_string()),
+                    encrypted: encrypted.then_some(true),
+                    ..Default::default()
+                };
+                patch.insert(key.to_string(), Some(VariablePatch::Fields(fields)));
+            }
+            for key in unset {
+                patch.insert(key, None);
+            }
+
+            let count = patch.len();
+            store.patch_environment(&project, &env, patch).await?;
+            println!("✓ Updated {} variable(s) in {}/{}", count, project, env);
+        }
+        EnvCommands::Get { args, env, raw } => {
+            let (project, [key]) = scope.split_args(args)?;
+            let env = scope.environment(env);
+            let variable = if raw {
+                store
+                    .get_raw_environment(&project, &env)
+                    .await?
+                    .remove(&key)
+                    .with_context(|| format!("Variable not found: {}", key))?
+            } else {
+                store.get_variable(&project, &env, &key).await?
+            };
             println!("{}={}", key, variable.value);
             if variable.encrypted {
                 println!("(encrypted)");
             }
+            if !variable.value_type.is_string() {
+                println!("(type: {})", variable.value_type);
+            }
+        }
+        EnvCommands::List {
+            project,
+            env,
+            explain: true,
+            ..
+        } => {
+            let project = scope.project(project)?;
+            let env = scope.environment(env);
+            let explained = store.explain_environment(&project, &env).await?;
+            if explained.is_empty() {
+                println!("No variables in {}/{}", project, env);
+            } else {
+                println!("Variables in {}/{}:", project, env);
+            }
+            for (key, var, source) in explained {
+                let value = if var.encrypted { "***" } else { &var.value };
+                println!("  {}={}  ({})", key, value, source);
+            }
         }
-        EnvCommands::List { project, env } => {
-            let environment: HashMap<String, EnvVariable> =
-                store.get_environment(&project, &env).await?;
+        EnvCommands::List {
+            project, env, raw, ..
+        } => {
+            let project = scope.project(project)?;
+            let env = scope.environment(env);
+            let environment: HashMap<String, EnvVariable> = if raw {
+                store.get_raw_environment(&project, &env).await?
+            } else {
+                store.get_environment(&project, &env).await?
+            };
             if environment.is_empty() {
                 println!("No variables in {}/{}", project, env);
             } else {
                 println!("Variables in {}/{}:", project, env);
-                for (key, var) in environment {
+                let mut vars: Vec<(String, EnvVariable)> = environment.into_iter().collect();
+                vars.sort_by(|a, b| a.0.cmp(&b.0));
+                for (key, var) in vars {
                     let value = if var.encrypted {
                         "***".to_string()
                     } else {
@@ -136,7 +408,9 @@ async fn handle_env_command(cmd: EnvCommands, config: &AppConfig) -> anyhow::Res
                 }
             }
         }
-        EnvCommands::Delete { project, key, env } => {
+        EnvCommands::Delete { args, env } => {
+            let (project, [key]) = scope.split_args(args)?;
+            let env = scope.environment(env);
             store.delete_variable(&project, &env, &key).await?;
             println!("✓ Deleted {} from {}/{}", key, project, env);
         }
@@ -144,23 +418,702 @@ async fn handle_env_command(cmd: EnvCommands, config: &AppConfig) -> anyhow::Res
             project,
             env,
             format,
+            options,
         } => {
+            let project = scope.project(project)?;
+            let env = scope.environment(env);
+            let format = format.unwrap_or_else(|| config.defaults.export_format.clone());
             let environment = store.get_environment(&project, &env).await?;
-            let output = match format.as_str() {
-                "dotenv" => routes::export_dotenv(&environment),
-                "json" => routes::export_json(&environment)?,
-                "yaml" => routes::export_yaml(&environment),
-                "docker" => routes::export_docker(&environment),
-                _ => anyhow::bail!("Unknown format: {}", format),
+
+            let ctx = formats::ExportContext {
+                project,
+                environment: env,
+                options: format_options(&options)?,
             };
+            let output = formats::registry().export(&format, &environment, &ctx)?;
             println!("{}", output);
         }
+        EnvCommands::Import {
+            args,
+            env,
+            format,
+            options,
+            strategy,
+            encrypted,
+            dry_run,
+        } => {
+            let (project, [file]) = scope.split_args(args)?;
+            let env = scope.environment(env);
+            let input = if file == "-" {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                std::fs::read_to_string(&file)
+                    .with_context(|| format!("Failed to read {}", file))?
+            };
+            let registry = formats::registry();
+            let format = match format {
+                Some(format) => format,
+                None => registry
+                    .importer_for_path(file.as_ref())
+                    .map_or("dotenv", |importer| importer.name())
+                    .to_string(),
+            };
+            let ctx = formats::ImportContext {
+                options: format_options(&options)?,
+            };
+
+            let vars = registry.import(&format, &input, &ctx)?;
+            let options = ImportOptions {
+                strategy,
+                encrypted,
+                dry_run,
+            };
+            let report = store
+                .import_variables(&project, &env, vars, options)
+                .await?;
+
+            for (marker, keys) in [
+                ("+", &report.added),
+                ("~", &report.changed),
+                ("=", &report.unchanged),
+                ("-", &report.skipped),
+            ] {
+                for key in keys {
+                    println!("  {} {}", marker, key);
+                }
+            }
+            println!(
+                "{} {}/{}: {} added, {} changed, {} unchanged, {} skipped",
+                if dry_run {
+                    "Would import into"
+                } else {
+                    "✓ Imported into"
+                },
+                project,
+                env,
+                report.added.len(),
+                report.changed.len(),
+                report.unchanged.len(),
+                report.skipped.len()
+            );
+        }
+        EnvCommands::Create {
+            args,
+            description,
+            parent,
+            protected,
+        } => {
+            let (project, [env]) = scope.split_args(args)?;
+            let settings = EnvironmentSettings {
+                parent,
+                description,
+                protected,
+                ..Default::default()
+            };
+            store
+                .create_environment(&project, &env, settings, None)
+                .await?;
+            println!("✓ Created environment {}/{}", project, env);
+        }
+        EnvCommands::Rename { args } => {
+            let (project, [env, new_name]) = scope.split_args(args)?;
+            store
+                .update_environment(&project, &env, Some(new_name.clone()), None, None)
+                .await?;
+            println!("✓ Renamed {}/{} to {}", project, env, new_name);
+        }
+        EnvCommands::Clone { args } => {
+            let (project, [from, to]) = scope.split_args(args)?;
+            store
+                .create_environment(&project, &to, EnvironmentSettings::default(), Some(&from))
+                .await?;
+            println!("✓ Cloned {}/{} to {}", project, from, to);
+        }
+        EnvCommands::Update {
+            args,
+            description,
+            protected,
+        } => {
+            let (project, [env]) = scope.split_args(args)?;
+            let settings = store
+                .update_environment(&project, &env, None, description, protected)
+                .await?;
+            println!(
+                "✓ Updated {}/{}{}",
+                project,
+                env,
+                if settings.protected {
+                    " (protected)"
+                } else {
+                    ""
+                }
+            );
+        }
+        EnvCommands::RemoveEnv { args } => {
+            let (project, [env]) = scope.split_args(args)?;
+            store.remove_environment(&project, &env).await?;
+            println!("✓ Removed environment {}/{}", project, env);
+        }
+        EnvCommands::Diff {
+            left,
+            right,
+            secrets,
+            exit_code,
+        } => {
+            let entries = diff::diff(
+                &diff_operand(store, &scope, &left).await?,
+                &diff_operand(store, &scope, &right).await?,
+                secrets,
+            );
+            if entries.is_empty() {
+                println!("No differences between {} and {}", left, right);
+            }
+            print_diff(&entries);
+            if exit_code && !entries.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        EnvCommands::Promote {
+            project,
+            from,
+            to,
+            include,
+            exclude,
+            prune,
+            yes,
+            dry_run,
+        } => {
+            let project = scope.project(project)?;
+            let mut options = PromoteOptions {
+                include,
+                exclude,
+                prune,
+                dry_run: true,
+            };
+            // The plan depends on both environments and the schema, so pin
+            // the whole project to the revision it was computed at
+            let target = db::Target::Project(project.clone());
+            let planned_at = store.revision(&target).await?;
+            let plan = store.promote(&project, &from, &to, &options).await?;
+            if plan.is_empty() {
+                println!("✓ {}/{} is up to date with {}", project, to, from);
+                return Ok(());
+            }
+
+            println!("Plan for {}/{} -> {}:", project, from, to);
+            print_diff(&plan);
+            let count = |change| plan.iter().filter(|entry| entry.change == change).count();
+            println!(
+                "{} to add, {} to change, {} to remove",
+                count(diff::Change::Added),
+                count(diff::Change::Changed),
+                count(diff::Change::Removed)
+            );
+            if dry_run {
+                return Ok(());
+            }
+            if !yes && !confirm(&format!("Apply to {}/{}?", project, to))? {
+                println!("Cancelled, nothing was changed");
+                return Ok(());
+            }
+
+            // Another process may have changed the project while we waited
+            store.reload().await?;
+            options.dry_run = false;
+            let applied = store
+                .with_precondition(db::Precondition {
+                    target,
+                    revisions: Some(vec![planned_at]),
+                })
+                .promote(&project, &from, &to, &options)
+                .await
+                .map_err(|e| match e {
+                    error::AppError::PreconditionFailed(_) => anyhow::anyhow!(
+                        "{} changed since the plan was made, nothing was applied; run the promotion again",
+                        project
+                    ),
+                    e => e.into(),
+                })?;
+            println!(
+                "✓ Applied {} change(s) from {} to {}/{}",
+                applied.len(),
+                from,
+                project,
+                to
+            );
+        }
+        EnvCommands::Inherit {
+            project, env, from, ..
+        } => {
+            let project = scope.project(project)?;
+            let env = scope.environment(env);
+            store.set_parent(&project, &env, from.clone()).await?;
+            match from {
+                Some(parent) => println!("✓ {}/{} now inherits from {}", project, env, parent),
+                None => println!("✓ {}/{} no longer inherits", project, env),
+            }
+        }
+        EnvCommands::Validate { project, env } => {
+            let project = scope.project(project)?;
+            let env = scope.environment(env);
+            let violations = store.validate_environment(&project, &env).await?;
+            if violations.is_empty() {
+                println!("✓ {}/{} matches the schema", project, env);
+            } else {
+                for violation in &violations {
+                    eprintln!("  ✗ {}: {}", violation.key, violation.message);
+                }
+                eprintln!(
+                    "{}/{}: {} schema violation(s)",
+                    project,
+                    env,
+                    violations.len()
+                );
+                std::process::exit(1);
+            }
+        }
     }
 
     Ok(())
 }
 
-// Make export functions public for CLI use
-// mod routes_export {
-//     pub use crate::routes::{export_docker, export_dotenv, export_json, export_yaml};
-// }
+async fn handle_schema_command(
+    cmd: SchemaCommands,
+    store: &JsonStore,
+    config: &AppConfig,
+) -> anyhow::Result<()> {
+    let scope = discover_scope(store, config).await?;
+
+    match cmd {
+        SchemaCommands::Set {
+            args,
+            r#type,
+            pattern,
+            allowed,
+            required_in,
+            default,
+        } => {
+            let (project, [key]) = scope.split_args(args)?;
+            let rule = SchemaRule {
+                value_type: r#type,
+                pattern,
+                allowed,
+                required_in,
+                default,
+            };
+            store.set_schema_rule(&project, key.clone(), rule).await?;
+            println!("✓ Set schema rule for {} in {}", key, project);
+        }
+        SchemaCommands::Remove { args } => {
+            let (project, [key]) = scope.split_args(args)?;
+            store.remove_schema_rule(&project, &key).await?;
+            println!("✓ Removed schema rule for {} from {}", key, project);
+        }
+        SchemaCommands::Show { project } => {
+            let project = scope.project(project)?;
+            let schema = store.get_project(&project).await?.schema;
+            if schema.is_empty() {
+                println!("No schema rules in {}", project);
+            }
+            for (key, rule) in schema {
+                let mut details = vec![rule.value_type.to_string()];
+                if let Some(pattern) = rule.pattern {
+                    details.push(format!("pattern /{}/", pattern));
+                }
+                if !rule.allowed.is_empty() {
+                    details.push(format!("one of {}", rule.allowed.join(", ")));
+                }
+                if !rule.required_in.is_empty() {
+                    details.push(format!("required in {}", rule.required_in.join(", ")));
+                }
+                if let Some(default) = rule.default {
+                    details.push(format!("default {:?}", default));
+                }
+                println!("  {}: {}", key, details.join("; "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_webhook_command(
+    cmd: WebhookCommands,
+    store: &JsonStore,
+    config: &AppConfig,
+) -> anyhow::Result<()> {
+    let scope = discover_scope(store, config).await?;
+
+    match cmd {
+        WebhookCommands::Add {
+            args,
+            events,
+            environments,
+            secret,
+        } => {
+            let (project, [url]) = scope.split_args(args)?;
+            let webhook = Webhook::new(url, secret, events, environments)
+                .map_err(|e| anyhow::anyhow!("Invalid webhook: {}", e))?;
+            let webhook = store.add_webhook(&project, webhook).await?;
+            println!("✓ Added webhook {} to {}", webhook.id, project);
+            println!("  URL: {}", webhook.url);
+            println!("  Secret: {}", webhook.secret);
+        }
+        WebhookCommands::List { project } => {
+            let project = scope.project(project)?;
+            let webhooks = store.list_webhooks(&project).await?;
+            if webhooks.is_empty() {
+                println!("No webhooks in {}", project);
+            }
+            for webhook in webhooks {
+                let events = match webhook.events.is_empty() {
+                    true => "all events".to_string(),
+                    false => webhook.events.join(", "),
+                };
+                let environments = match webhook.environments.is_empty() {
+                    true => "all environments".to_string(),
+                    false => webhook.environments.join(", "),
+                };
+                println!("  • {} {}", webhook.id, webhook.url);
+                println!("    {} in {}", events, environments);
+            }
+        }
+        WebhookCommands::Remove { args } => {
+            let (project, [id]) = scope.split_args(args)?;
+            store.remove_webhook(&project, &id).await?;
+            println!("✓ Removed webhook {} from {}", id, project);
+        }
+        WebhookCommands::Test { args } => {
+            let (project, [id]) = scope.split_args(args)?;
+            let webhook = store.get_webhook(&project, &id).await?;
+            let ping = ChangeEvent {
+                revision: store
+                    .revision(&db::Target::Project(project.clone()))
+                    .await?,
+                kind: ChangeKind::Ping,
+                project: project.clone(),
+                environment: None,
+                keys: Vec::new(),
+                webhooks: Vec::new(),
+            };
+            let delivery = webhook::Sender::new(store.clone(), webhook::Policy::TEST)
+                .deliver(&webhook, &ping)
+                .await;
+            store
+                .record_deliveries(vec![(project, delivery.clone())])
+                .await?;
+            match (delivery.status, delivery.error) {
+                (Some(status), _) if delivery.success => {
+                    println!("✓ {} responded {}", webhook.url, status)
+                }
+                (Some(status), _) => anyhow::bail!("{} responded {}", webhook.url, status),
+                (None, error) => anyhow::bail!(
+                    "Failed to reach {}: {}",
+                    webhook.url,
+                    error.unwrap_or_default()
+                ),
+            }
+        }
+        WebhookCommands::Deliveries {
+            project,
+            webhook,
+            limit,
+        } => {
+            let project = scope.project(project)?;
+            let deliveries = store.list_deliveries(&project).await?;
+            let deliveries: Vec<_> = deliveries
+                .into_iter()
+                .rev()
+                .filter(|delivery| webhook.as_ref().is_none_or(|id| delivery.webhook == *id))
+                .take(limit)
+                .collect();
+            if deliveries.is_empty() {
+                println!("No deliveries in {}", project);
+            }
+            for delivery in deliveries {
+                let outcome = match (delivery.status, &delivery.error) {
+                    (Some(status), _) => status.to_string(),
+                    (None, Some(error)) => error.clone(),
+                    (None, None) => "no response".to_string(),
+                };
+                println!(
+                    "  {} {} {} {} r{} → {} ({} attempt(s))",
+                    if delivery.success { "✓" } else { "✗" },
+                    delivery.delivered_at.format("%Y-%m-%d %H:%M:%S"),
+                    delivery.webhook,
+                    delivery.event,
+                    delivery.revision,
+                    outcome,
+                    delivery.attempts
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn apply_manifest(
+    file: &std::path::Path,
+    yes: bool,
+    store: &JsonStore,
+) -> anyhow::Result<()> {
+    let actions = manifest::plan(&load_manifest(file)?, store).await?;
+    if print_plan(&actions) {
+        return Ok(());
+    }
+    if !yes && !confirm("Apply these changes?")? {
+        println!("Cancelled, nothing was changed");
+        return Ok(());
+    }
+    for (done, action) in actions.iter().enumerate() {
+        action.apply(store).await.with_context(|| {
+            format!(
+                "Failed to apply `{}` after {} of {} changes",
+                action,
+                done,
+                actions.len()
+            )
+        })?;
+    }
+    println!("✓ Applied {} change(s)", actions.len());
+
+    Ok(())
+}
+
+fn load_manifest(path: &std::path::Path) -> anyhow::Result<manifest::Manifest> {
+    let input = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    manifest::Manifest::parse(&input).with_context(|| format!("Failed to load {}", path.display()))
+}
+
+/// Prints the plan and its totals; returns whether there was nothing to do.
+fn print_plan(actions: &[manifest::Action]) -> bool {
+    if actions.is_empty() {
+        println!("✓ No changes, the store matches the manifest");
+        return true;
+    }
+    for action in actions {
+        println!("{}", action);
+    }
+    let count = |change| {
+        actions
+            .iter()
+            .filter(|action| action.change() == change)
+            .count()
+    };
+    println!(
+        "{} to add, {} to change, {} to remove",
+        count(diff::Change::Added),
+        count(diff::Change::Changed),
+        count(diff::Change::Removed)
+    );
+    false
+}
+
+fn print_diff(entries: &[diff::DiffEntry]) {
+    for entry in entries {
+        match (&entry.left, &entry.right) {
+            (Some(l), Some(r)) => {
+                println!("{} {}: {} -> {}", entry.change.marker(), entry.key, l, r)
+            }
+            (Some(value), None) | (None, Some(value)) => {
+                println!("{} {}={}", entry.change.marker(), entry.key, value)
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Asks a yes/no question on stdin; anything but `y` or `yes` is a no.
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    print!("{} [y/N] ", question);
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// One side of `env diff`: an existing file is read as an export snapshot,
+/// `PROJECT/ENV` names an environment, and a bare `ENV` belongs to the bound
+/// project.
+async fn diff_operand(
+    store: &JsonStore,
+    scope: &Scope,
+    operand: &str,
+) -> anyhow::Result<HashMap<String, EnvVariable>> {
+    if let Some(path) = operand_file(operand) {
+        let input =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", operand))?;
+        let registry = formats::registry();
+        let format = registry
+            .importer_for_path(path)
+            .map_or("dotenv", |importer| importer.name());
+        let vars = registry.import(format, &input, &formats::ImportContext::default())?;
+        return Ok(vars
+            .into_iter()
+            .map(|(key, value)| (key, EnvVariable::new(value, false)))
+            .collect());
+    }
+
+    let (project, env) = match operand.split_once('/') {
+        Some((project, env)) => (project.to_string(), env.to_string()),
+        None => (scope.project(None)?, operand.to_string()),
+    };
+    Ok(store.get_environment(&project, &env).await?)
+}
+
+/// The file a `diff` operand names, if it names one. Files need a marker, so
+/// an environment named like a file in the working directory isn't read from
+/// disk: a `file:` prefix, an absolute, `./` or `../` path, or a `.` in the
+/// name of an existing file, as in `prod.env`.
+fn operand_file(operand: &str) -> Option<&std::path::Path> {
+    if let Some(path) = operand.strip_prefix("file:") {
+        return Some(std::path::Path::new(path));
+    }
+    let path = std::path::Path::new(operand);
+    let explicit = ["/", "./", "../"]
+        .iter()
+        .any(|prefix| operand.starts_with(prefix));
+    let named_like_file = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains('.'));
+    (explicit || (named_like_file && path.is_file())).then_some(path)
+}
+
+/// Parses repeated `-o KEY=VALUE` flags into format options.
+fn format_options(options: &[String]) -> anyhow::Result<BTreeMap<String, String>> {
+    options
+        .iter()
+        .map(|option| {
+            option
+                .split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .with_context(|| format!("Expected KEY=VALUE, got {}", option))
+        })
+        .collect()
+}
+
+async fn run_command(
+    project: Option<String>,
+    env: Option<String>,
+    override_existing: bool,
+    redact: bool,
+    command: Vec<String>,
+    config: &AppConfig,
+) -> anyhow::Result<i32> {
+    let store = JsonStore::new(config.database.path.clone())?;
+    let scope = Scope::discover(config.defaults.environment.clone())?;
+    let project = scope.project(project)?;
+    let env = scope.environment(env);
+
+    let environment = store.get_environment(&project, &env).await?;
+    let cmd = runner::command(&environment, &command, override_existing)?;
+    let code = if redact {
+        let redactor = runner::Redactor::new(&environment);
+        runner::spawn_redacted(cmd, redactor).await
+    } else {
+        runner::exec(cmd)
+    };
+    code.with_context(|| format!("Failed to run {}", command[0]))
+}
+
+fn locate_binding(path: Option<PathBuf>) -> anyhow::Result<ProjectBinding> {
+    let path = match path {
+        Some(path) => path,
+        None => std::env::current_dir()?,
+    };
+    Ok(ProjectBinding::locate(&path)?)
+}
+
+/// Shell code moving the shell from what it last loaded to what the current
+/// directory's binding calls for. Prints nothing when nothing changed.
+async fn hook_env(shell: shell::Shell, config: &AppConfig) -> anyhow::Result<String> {
+    let state = hook::HookState::from_env();
+    let binding = ProjectBinding::discover(&std::env::current_dir()?)?;
+
+    let target = match &binding {
+        Some(binding) => {
+            let env = binding
+                .environment
+                .clone()
+                .unwrap_or_else(|| config.defaults.environment.clone());
+            let allowed = hook::AllowList::open().is_allowed(&binding.path)?;
+            Some((binding, env, allowed))
+        }
+        None => None,
+    };
+
+    match (&state, &target) {
+        (None, None) => return Ok(String::new()),
+        // A failed load is retried quietly at every prompt until it works
+        (Some(state), Some((binding, env, allowed)))
+            if state.matches(binding, env, *allowed) && state.error.is_none() =>
+        {
+            return Ok(String::new());
+        }
+        _ => {}
+    }
+
+    let mut out = String::new();
+    if let Some(state) = &state {
+        out.push_str(&state.unload(shell));
+    }
+
+    if let Some((binding, env, allowed)) = target {
+        if allowed {
+            let loaded = async {
+                let store = JsonStore::new(config.database.path.clone())?;
+                store.get_environment(&binding.project, &env).await
+            };
+            match loaded.await {
+                Ok(environment) => out.push_str(&hook::load(
+                    shell,
+                    binding,
+                    &env,
+                    &environment,
+                    state.as_ref(),
+                )?),
+                Err(e) => {
+                    let error = e.to_string();
+                    let reported = state.as_ref().is_some_and(|state| {
+                        state.matches(binding, &env, true) && state.error.as_ref() == Some(&error)
+                    });
+                    if reported {
+                        return Ok(String::new());
+                    }
+                    eprintln!(
+                        "rusty: failed to load {}/{}: {}",
+                        binding.project, env, error
+                    );
+                    // The previous directory's variables are still unloaded
+                    out.push_str(&hook::failed(shell, binding, &env, &error)?);
+                }
+            }
+        } else {
+            eprintln!(
+                "rusty: {} is not allowed. Run `rusty allow` to load it.",
+                binding.path.display()
+            );
+            out.push_str(&hook::blocked(shell, binding, &env)?);
+        }
+    }
+
+    Ok(out)
+}
+
+fn handle_config_command(cmd: ConfigCommands, config: &AppConfig) -> anyhow::Result<()> {
+    match cmd {
+        ConfigCommands::Show { origin } => {
+            for (key, value, source) in config.entries()? {
+                if origin {
+                    println!("{} = {}  ({})", key, value, source);
+                } else {
+                    println!("{} = {}", key, value);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,652 @@
+use crate::db::JsonStore;
+use crate::diff::Change;
+use crate::error::{AppError, Result};
+use crate::models::{EnvironmentSettings, Project, Schema, SchemaRule, VariableType};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// Desired state for the projects it lists. Anything a manifest leaves out
+/// (other projects, environments, an omitted `schema` or `variables`) is
+/// left unmanaged, and encrypted variables are never touched, so secrets
+/// stay out of the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub projects: BTreeMap<String, ProjectManifest>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectManifest {
+    pub description: Option<String>,
+    pub schema: Option<Schema>,
+    #[serde(default)]
+    pub environments: BTreeMap<String, EnvironmentManifest>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentManifest {
+    /// `parent: ~` removes the parent; leaving the field out leaves it as is
+    #[serde(default, deserialize_with = "present")]
+    pub parent: Option<Option<String>>,
+    pub description: Option<String>,
+    pub protected: Option<bool>,
+    pub variables: Option<BTreeMap<String, ManifestValue>>,
+}
+
+/// Tells a field given as null (`Some(None)`) from one left out (`None`).
+fn present<'de, D, T>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// `KEY: value`, or `KEY: { value: ..., type: port }` to also pin the type.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ManifestValue {
+    Plain(Scalar),
+    Typed(TypedValue),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TypedValue {
+    pub value: Scalar,
+    #[serde(rename = "type")]
+    pub value_type: Option<VariableType>,
+}
+
+/// Numbers and booleans are accepted so values don't need quoting.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Scalar {
+    String(String),
+    Number(serde_json::Number),
+    Bool(bool),
+}
+
+impl ManifestValue {
+    fn parts(&self) -> (String, Option<VariableType>) {
+        match self {
+            Self::Plain(value) => (value.to_string(), None),
+            Self::Typed(typed) => (typed.value.to_string(), typed.value_type),
+        }
+    }
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(s) => f.write_str(s),
+            Self::Number(n) => write!(f, "{}", n),
+            Self::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn parse(input: &str) -> Result<Self> {
+        let docs = YamlLoader::load_from_str(input)
+            .map_err(|e| AppError::InvalidInput(format!("invalid YAML: {}", e)))?;
+        let value = match docs.first() {
+            Some(doc) => to_json(doc)?,
+            None => return Ok(Self::default()),
+        };
+        serde_json::from_value(value)
+            .map_err(|e| AppError::InvalidInput(format!("invalid manifest: {}", e)))
+    }
+}
+
+/// One store operation needed to reach the manifest's state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    CreateProject {
+        project: String,
+        description: Option<String>,
+    },
+    UpdateProject {
+        project: String,
+        description: String,
+    },
+    SetSchemaRule {
+        project: String,
+        key: String,
+        rule: SchemaRule,
+    },
+    RemoveSchemaRule {
+        project: String,
+        key: String,
+    },
+    CreateEnvironment {
+        project: String,
+        env: String,
+        settings: EnvironmentSettings,
+    },
+    UpdateEnvironment {
+        project: String,
+        env: String,
+        description: Option<String>,
+        protected: Option<bool>,
+    },
+    SetParent {
+        project: String,
+        env: String,
+        parent: Option<String>,
+    },
+    SetVariable {
+        project: String,
+        env: String,
+        key: String,
+        value: String,
+        value_type: Option<VariableType>,
+        /// Shown in the plan; `***` if it was encrypted
+        old: Option<String>,
+    },
+    RemoveVariable {
+        project: String,
+        env: String,
+        key: String,
+        old: String,
+    },
+}
+
+impl Action {
+    pub fn change(&self) -> Change {
+        match self {
+            Self::CreateProject { .. } | Self::CreateEnvironment { .. } => Change::Added,
+            Self::SetSchemaRule { .. } | Self::SetVariable { old: None, .. } => Change::Added,
+            Self::RemoveSchemaRule { .. } | Self::RemoveVariable { .. } => Change::Removed,
+            Self::UpdateProject { .. }
+            | Self::UpdateEnvironment { .. }
+            | Self::SetParent { .. }
+            | Self::SetVariable { .. } => Change::Changed,
+        }
+    }
+
+    /// Runs the action through the same store operations the CLI uses.
+    pub async fn apply(&self, store: &JsonStore) -> Result<()> {
+        match self.clone() {
+            Self::CreateProject {
+                project,
+                description,
+            } => {
+                store.create_project(project, description).await?;
+            }
+            Self::UpdateProject {
+                project,
+                description,
+            } => {
+                store
+                    .update_project(&project, None, Some(description))
+                    .await?;
+            }
+            Self::SetSchemaRule { project, key, rule } => {
+                store.set_schema_rule(&project, key, rule).await?;
+            }
+            Self::RemoveSchemaRule { project, key } => {
+                store.remove_schema_rule(&project, &key).await?;
+            }
+            Self::CreateEnvironment {
+                project,
+                env,
+                settings,
+            } => {
+                store
+                    .create_environment(&project, &env, settings, None)
+                    .await?;
+            }
+            Self::UpdateEnvironment {
+                project,
+                env,
+                description,
+                protected,
+            } => {
+                store
+                    .update_environment(&project, &env, None, description, protected)
+                    .await?;
+            }
+            Self::SetParent {
+                project,
+                env,
+                parent,
+            } => store.set_parent(&project, &env, parent).await?,
+            Self::SetVariable {
+                project,
+                env,
+                key,
+                value,
+                value_type,
+                ..
+            } => {
+                store
+                    .set_variable(&project, &env, key, value, false, value_type)
+                    .await?;
+            }
+            Self::RemoveVariable {
+                project, env, key, ..
+            } => store.delete_variable(&project, &env, &key).await?,
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.change().marker())?;
+        match self {
+            Self::CreateProject { project, .. } => write!(f, "project {}", project),
+            Self::UpdateProject {
+                project,
+                description,
+            } => write!(f, "project {}: description {:?}", project, description),
+            Self::SetSchemaRule { project, key, rule } => write!(
+                f,
+                "schema {}/{}: {}",
+                project,
+                key,
+                serde_json::to_string(rule).map_err(|_| fmt::Error)?
+            ),
+            Self::RemoveSchemaRule { project, key } => write!(f, "schema {}/{}", project, key),
+            Self::CreateEnvironment { project, env, .. } => {
+                write!(f, "environment {}/{}", project, env)
+            }
+            Self::UpdateEnvironment {
+                project,
+                env,
+                description,
+                protected,
+            } => {
+                write!(f, "environment {}/{}:", project, env)?;
+                if let Some(description) = description {
+                    write!(f, " description {:?}", description)?;
+                }
+                if let Some(protected) = protected {
+                    write!(f, " protected {}", protected)?;
+                }
+                Ok(())
+            }
+            Self::SetParent {
+                project,
+                env,
+                parent,
+            } => write!(
+                f,
+                "environment {}/{}: inherits {}",
+                project,
+                env,
+                parent.as_deref().unwrap_or("nothing")
+            ),
+            Self::SetVariable {
+                project,
+                env,
+                key,
+                value,
+                old,
+                ..
+            } => match old {
+                Some(old) => write!(f, "{}/{} {}: {} -> {}", project, env, key, old, value),
+                None => write!(f, "{}/{} {}={}", project, env, key, value),
+            },
+            Self::RemoveVariable {
+                project,
+                env,
+                key,
+                old,
+            } => write!(f, "{}/{} {}={}", project, env, key, old),
+        }
+    }
+}
+
+/// The actions that bring the store in line with `manifest`, in the order
+/// they must be applied.
+pub async fn plan(manifest: &Manifest, store: &JsonStore) -> Result<Vec<Action>> {
+    let mut actions = Vec::new();
+    for (name, desired) in &manifest.projects {
+        let current = match store.get_project(name).await {
+            Ok(project) => Some(project),
+            Err(AppError::ProjectNotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        actions.extend(plan_project(name, desired, current.as_ref()));
+    }
+    Ok(actions)
+}
+
+/// Schema changes come before variables so new values are checked against
+/// the new rules, and parents are set once every environment exists.
+fn plan_project(name: &str, desired: &ProjectManifest, current: Option<&Project>) -> Vec<Action> {
+    let project = name.to_string();
+    let mut actions = Vec::new();
+
+    match current {
+        None => actions.push(Action::CreateProject {
+            project: project.clone(),
+            description: desired.description.clone(),
+        }),
+        Some(current) => {
+            if let Some(description) = &desired.description
+                && current.description.as_ref() != Some(description)
+            {
+                actions.push(Action::UpdateProject {
+                    project: project.clone(),
+                    description: description.clone(),
+                });
+            }
+        }
+    }
+
+    if let Some(schema) = &desired.schema {
+        let current_schema = current.map(|p| p.schema.clone()).unwrap_or_default();
+        for key in current_schema
+            .keys()
+            .filter(|key| !schema.contains_key(*key))
+        {
+            actions.push(Action::RemoveSchemaRule {
+                project: project.clone(),
+                key: key.clone(),
+            });
+        }
+        for (key, rule) in schema {
+            if current_schema.get(key) != Some(rule) {
+                actions.push(Action::SetSchemaRule {
+                    project: project.clone(),
+                    key: key.clone(),
+                    rule: rule.clone(),
+                });
+            }
+        }
+    }
+
+    let settings_of = |env: &str| {
+        current
+            .and_then(|p| p.environment_settings.get(env))
+            .cloned()
+            .unwrap_or_default()
+    };
+    let exists = |env: &str| current.is_some_and(|p| p.environments.contains_key(env));
+
+    for (env, desired_env) in &desired.environments {
+        if !exists(env) {
+            actions.push(Action::CreateEnvironment {
+                project: project.clone(),
+                env: env.clone(),
+                settings: EnvironmentSettings {
+                    parent: None,
+                    description: desired_env.description.clone(),
+                    protected: desired_env.protected.unwrap_or_default(),
+                    ..Default::default()
+                },
+            });
+            continue;
+        }
+        let settings = settings_of(env);
+        let description = desired_env
+            .description
+            .clone()
+            .filter(|description| settings.description.as_ref() != Some(description));
+        let protected = desired_env
+            .protected
+            .filter(|protected| *protected != settings.protected);
+        if description.is_some() || protected.is_some() {
+            actions.push(Action::UpdateEnvironment {
+                project: project.clone(),
+                env: env.clone(),
+                description,
+                protected,
+            });
+        }
+    }
+
+    for (env, desired_env) in &desired.environments {
+        let Some(parent) = &desired_env.parent else {
+            continue;
+        };
+        if *parent != settings_of(env).parent {
+            actions.push(Action::SetParent {
+                project: project.clone(),
+                env: env.clone(),
+                parent: parent.clone(),
+            });
+        }
+    }
+
+    for (env, desired_env) in &desired.environments {
+        let Some(variables) = &desired_env.variables else {
+            continue;
+        };
+        let stored = current
+            .and_then(|p| p.environments.get(env))
+            .cloned()
+            .unwrap_or_default();
+
+        for (key, desired_value) in variables {
+            let (value, value_type) = desired_value.parts();
+            let old = match stored.get(key) {
+                None => None,
+                // Applying would store the secret in plain text
+                Some(var) if var.encrypted => continue,
+                Some(var)
+                    if var.value == value && value_type.is_none_or(|t| t == var.value_type) =>
+                {
+                    continue;
+                }
+                Some(var) => Some(var.value.clone()),
+            };
+            actions.push(Action::SetVariable {
+                project: project.clone(),
+                env: env.clone(),
+                key: key.clone(),
+                value,
+                value_type,
+                old,
+            });
+        }
+
+        let mut removed: Vec<(&String, &String)> = stored
+            .iter()
+            .filter(|(key, var)| !var.encrypted && !variables.contains_key(*key))
+            .map(|(key, var)| (key, &var.value))
+            .collect();
+        removed.sort();
+        for (key, old) in removed {
+            actions.push(Action::RemoveVariable {
+                project: project.clone(),
+                env: env.clone(),
+                key: key.clone(),
+                old: old.clone(),
+            });
+        }
+    }
+
+    actions
+}
+
+/// YAML to JSON so manifests and agent configs can be read with serde. Floats keep their
+/// text as strings, so `1.10` isn't turned into `1.1`.
+pub fn to_json(node: &Yaml) -> Result<serde_json::Value> {
+    Ok(match node {
+        Yaml::Hash(hash) => {
+            let mut map = serde_json::Map::new();
+            for (key, value) in hash {
+                let key = match key {
+                    Yaml::String(s) | Yaml::Real(s) => s.clone(),
+                    Yaml::Integer(i) => i.to_string(),
+                    Yaml::Boolean(b) => b.to_string(),
+                    _ => {
+                        return Err(AppError::InvalidInput(
+                            "YAML keys must be scalars".to_string(),
+                        ));
+                    }
+                };
+                map.insert(key, to_json(value)?);
+            }
+            serde_json::Value::Object(map)
+        }
+        Yaml::Array(items) => items.iter().map(to_json).collect::<Result<_>>()?,
+        Yaml::String(s) | Yaml::Real(s) => s.clone().into(),
+        Yaml::Integer(i) => (*i).into(),
+        Yaml::Boolean(b) => (*b).into(),
+        Yaml::Null => serde_json::Value::Null,
+        Yaml::Alias(_) | Yaml::BadValue => {
+            return Err(AppError::InvalidInput("unsupported YAML value".to_string()));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvVariable;
+
+    const MANIFEST: &str = r#"
+projects:
+  app:
+    description: Web app
+    schema:
+      PORT: { type: port, required_in: ["*"] }
+    environments:
+      base:
+        variables:
+          PORT: 8080
+          LOG_LEVEL: info
+      staging:
+        parent: base
+        protected: true
+        variables:
+          LOG_LEVEL: { value: debug, type: string }
+"#;
+
+    fn describe(actions: &[Action]) -> Vec<String> {
+        actions.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn plans_a_new_project_in_order() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let actions = plan_project("app", &manifest.projects["app"], None);
+        assert_eq!(
+            describe(&actions),
+            [
+                "+ project app",
+                r#"+ schema app/PORT: {"type":"port","required_in":["*"]}"#,
+                "+ environment app/base",
+                "+ environment app/staging",
+                "~ environment app/staging: inherits base",
+                "+ app/base LOG_LEVEL=info",
+                "+ app/base PORT=8080",
+                "+ app/staging LOG_LEVEL=debug",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_drift_but_leaves_secrets_alone() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let mut project = Project::new("app".to_string(), Some("Web app".to_string()));
+        project.schema = manifest.projects["app"].schema.clone().unwrap();
+        let var = |value: &str, encrypted| EnvVariable::new(value.to_string(), encrypted);
+        project.environments.insert(
+            "base".to_string(),
+            [
+                ("PORT".to_string(), var("8080", false)),
+                ("LOG_LEVEL".to_string(), var("warn", false)),
+                ("DEBUG".to_string(), var("1", false)),
+                ("API_KEY".to_string(), var("secret", true)),
+            ]
+            .into(),
+        );
+        project.environments.insert(
+            "staging".to_string(),
+            [("LOG_LEVEL".to_string(), var("debug", false))].into(),
+        );
+        project.environment_settings.insert(
+            "staging".to_string(),
+            EnvironmentSettings {
+                parent: Some("base".to_string()),
+                protected: true,
+                ..Default::default()
+            },
+        );
+
+        let actions = plan_project("app", &manifest.projects["app"], Some(&project));
+        assert_eq!(
+            describe(&actions),
+            ["~ app/base LOG_LEVEL: warn -> info", "- app/base DEBUG=1"]
+        );
+    }
+
+    #[test]
+    fn never_rewrites_encrypted_variables() {
+        let manifest = Manifest::parse(
+            "projects:\n  app:\n    environments:\n      prod:\n        variables:\n          API_KEY: s3cret\n          OTHER_KEY: changed\n",
+        )
+        .unwrap();
+        let mut project = Project::new("app".to_string(), None);
+        project.environments.insert(
+            "prod".to_string(),
+            [
+                (
+                    "API_KEY".to_string(),
+                    EnvVariable::new("s3cret".to_string(), true),
+                ),
+                (
+                    "OTHER_KEY".to_string(),
+                    EnvVariable::new("old".to_string(), true),
+                ),
+            ]
+            .into(),
+        );
+
+        assert!(plan_project("app", &manifest.projects["app"], Some(&project)).is_empty());
+    }
+
+    #[test]
+    fn leaves_omitted_settings_unmanaged() {
+        let mut project = Project::new("app".to_string(), None);
+        for env in ["base", "prod"] {
+            project
+                .environments
+                .insert(env.to_string(), Default::default());
+        }
+        project.environment_settings.insert(
+            "prod".to_string(),
+            EnvironmentSettings {
+                parent: Some("base".to_string()),
+                protected: true,
+                ..Default::default()
+            },
+        );
+        let plan = |yaml: &str| {
+            let manifest = Manifest::parse(yaml).unwrap();
+            describe(&plan_project(
+                "app",
+                &manifest.projects["app"],
+                Some(&project),
+            ))
+        };
+
+        assert!(plan("projects:\n  app:\n    environments:\n      prod: {}\n").is_empty());
+        assert_eq!(
+            plan(
+                "projects:\n  app:\n    environments:\n      prod:\n        parent: ~\n        protected: false\n"
+            ),
+            [
+                "~ environment app/prod: protected false",
+                "~ environment app/prod: inherits nothing",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(Manifest::parse("projects:\n  app:\n    envs: {}\n").is_err());
+        assert!(Manifest::parse("").unwrap().projects.is_empty());
+    }
+}
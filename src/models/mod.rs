@@ -1,10 +1,24 @@
+pub mod schema;
+mod value_type;
+mod webhook;
+
+pub use schema::{Schema, SchemaRule, Violation};
+pub use value_type::{VariableType, parse_duration};
+pub use webhook::{Delivery, ProjectWebhooks, Webhook, WebhookInfo};
+
+use crate::diff::SecretDisplay;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnvVariable {
     pub value: String,
     pub encrypted: bool,
+    #[serde(rename = "type", default)]
+    pub value_type: VariableType,
+    /// Database revision of the last change, see [`Database::next_revision`]
+    #[serde(default)]
+    pub revision: u64,
     #[serde(with = "chrono::serde::ts_seconds")]
     pub created_at: chrono::DateTime<chrono::Utc>,
     #[serde(with = "chrono::serde::ts_seconds")]
@@ -17,6 +31,8 @@ impl EnvVariable {
         Self {
             value,
             encrypted,
+            value_type: VariableType::default(),
+            revision: 0,
             created_at: now,
             updated_at: now,
         }
@@ -25,12 +41,38 @@ impl EnvVariable {
 
 pub type Environment = HashMap<String, EnvVariable>;
 
+/// Per-environment settings kept beside its variables.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentSettings {
+    /// Environment whose variables this one inherits and overrides
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Protected environments can't be renamed or removed
+    pub protected: bool,
+    /// Revision of the last change to the environment or its variables
+    pub revision: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Project {
     pub id: String,
     pub name: String,
     pub description: Option<String>,
     pub environments: HashMap<String, Environment>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub environment_settings: HashMap<String, EnvironmentSettings>,
+    #[serde(default)]
+    pub schema: Schema,
+    /// Revision of the last change to anything in the project
+    #[serde(default)]
+    pub revision: u64,
+    /// Revision of the last change to the schema, whose defaults show in
+    /// every environment
+    #[serde(default)]
+    pub schema_revision: u64,
     #[serde(with = "chrono::serde::ts_seconds")]
     pub created_at: chrono::DateTime<chrono::Utc>,
     #[serde(with = "chrono::serde::ts_seconds")]
@@ -45,19 +87,44 @@ impl Project {
             name,
             description,
             environments: HashMap::new(),
+            environment_settings: HashMap::new(),
+            schema: Schema::new(),
+            revision: 0,
+            schema_revision: 0,
             created_at: now,
             updated_at: now,
         }
     }
 
-    pub fn update_timestamp(&mut self) {
+    /// Records a change to the project made at `revision`.
+    pub fn touch(&mut self, revision: u64) {
+        self.revision = revision;
         self.updated_at = chrono::Utc::now();
     }
+
+    /// Records a change to the schema, and so to the project, made at
+    /// `revision`.
+    pub fn touch_schema(&mut self, revision: u64) {
+        self.schema_revision = revision;
+        self.touch(revision);
+    }
+
+    /// Records a change to `env`, and so to the project, made at `revision`.
+    pub fn touch_environment(&mut self, env: &str, revision: u64) {
+        self.environment_settings
+            .entry(env.to_string())
+            .or_default()
+            .revision = revision;
+        self.touch(revision);
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metadata {
     pub version: String,
+    /// Revision of the latest change to anything
+    #[serde(default)]
+    pub revision: u64,
     #[serde(with = "chrono::serde::ts_seconds")]
     pub last_backup: chrono::DateTime<chrono::Utc>,
 }
@@ -66,6 +133,7 @@ impl Default for Metadata {
     fn default() -> Self {
         Self {
             version: "1.0.0".to_string(),
+            revision: 0,
             last_backup: chrono::Utc::now(),
         }
     }
@@ -74,9 +142,21 @@ impl Default for Metadata {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Database {
     pub projects: HashMap<String, Project>,
+    /// Keyed by project name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub webhooks: HashMap<String, ProjectWebhooks>,
     pub metadata: Metadata,
 }
 
+impl Database {
+    /// The revision the next change will be recorded at. Revisions only
+    /// ever increase, so a project, environment or variable changed since
+    /// it was read always has a different one.
+    pub fn next_revision(&self) -> u64 {
+        self.metadata.revision + 1
+    }
+}
+
 // API Request/Response types
 #[derive(Debug, Deserialize)]
 pub struct CreateProjectRequest {
@@ -84,6 +164,16 @@ pub struct CreateProjectRequest {
     pub description: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub environments: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct UpdateProjectRequest {
     pub name: Option<String>,
@@ -94,10 +184,219 @@ pub struct UpdateProjectRequest {
 pub struct SetVariableRequest {
     pub value: String,
     pub encrypted: Option<bool>,
+    /// Keeps the variable's current type when omitted
+    #[serde(rename = "type")]
+    pub value_type: Option<VariableType>,
+}
+
+/// Body of `PATCH .../envs/{env}`: a JSON Merge Patch over the environment's
+/// variables, where `null` deletes a key. Settings are patched at
+/// `.../envs/{env}/settings`, and their field names are refused here.
+pub type EnvironmentPatch = BTreeMap<String, Option<VariablePatch>>;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum VariablePatch {
+    /// Shorthand for `{"value": ...}`
+    Value(String),
+    Fields(VariableFields),
+}
+
+/// Fields to change on a variable; omitted ones keep their current value.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariableFields {
+    pub value: Option<String>,
+    pub encrypted: Option<bool>,
+    #[serde(rename = "type")]
+    pub value_type: Option<VariableType>,
+}
+
+impl VariablePatch {
+    pub fn into_fields(self) -> VariableFields {
+        match self {
+            Self::Value(value) => VariableFields {
+                value: Some(value),
+                ..Default::default()
+            },
+            Self::Fields(fields) => fields,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    /// Replace existing values
+    #[default]
+    Overwrite,
+    /// Keep existing values and only add new keys
+    SkipExisting,
+    /// Abort if any existing key would change
+    FailOnConflict,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImportOptions {
+    pub strategy: MergeStrategy,
+    pub encrypted: bool,
+    pub dry_run: bool,
+}
+
+/// Which keys `env promote` copies, and whether it removes keys the source
+/// doesn't have.
+#[derive(Debug, Clone, Default)]
+pub struct PromoteOptions {
+    /// Glob patterns; empty means every key
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub prune: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    pub format: Option<String>,
+    pub strategy: Option<MergeStrategy>,
+    pub encrypted: Option<bool>,
+    pub dry_run: Option<bool>,
+    /// Separator nested documents are flattened with
+    pub separator: Option<String>,
+}
+
+/// Keys touched by an import, grouped by what happened to them.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub skipped: Vec<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EnvironmentQuery {
+    /// Return stored values without resolving `${...}` references
+    #[serde(default)]
+    pub raw: bool,
+    /// Include where each value came from
+    #[serde(default)]
+    pub explain: bool,
+    /// Block until the environment's index is greater than this
+    pub index: Option<u64>,
+    /// How long to block for, e.g. `30s` (default 5m, at most 10m)
+    pub wait: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    /// Only send events for this project
+    pub project: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "&'static str")]
+pub enum ChangeKind {
+    ProjectCreated,
+    ProjectUpdated,
+    ProjectDeleted,
+    EnvironmentCreated,
+    EnvironmentUpdated,
+    EnvironmentRemoved,
+    VariablesChanged,
+    SchemaChanged,
+    /// Sent by `webhook test`, never by the store
+    Ping,
+}
+
+impl ChangeKind {
+    /// Every kind the store emits.
+    pub const ALL: [Self; 8] = [
+        Self::ProjectCreated,
+        Self::ProjectUpdated,
+        Self::ProjectDeleted,
+        Self::EnvironmentCreated,
+        Self::EnvironmentUpdated,
+        Self::EnvironmentRemoved,
+        Self::VariablesChanged,
+        Self::SchemaChanged,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ProjectCreated => "project.created",
+            Self::ProjectUpdated => "project.updated",
+            Self::ProjectDeleted => "project.deleted",
+            Self::EnvironmentCreated => "environment.created",
+            Self::EnvironmentUpdated => "environment.updated",
+            Self::EnvironmentRemoved => "environment.removed",
+            Self::VariablesChanged => "variables.changed",
+            Self::SchemaChanged => "schema.changed",
+            Self::Ping => "ping",
+        }
+    }
+}
+
+impl From<ChangeKind> for &'static str {
+    fn from(kind: ChangeKind) -> Self {
+        kind.name()
+    }
+}
+
+/// A change made through the store, as sent to watchers.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangeEvent {
+    pub revision: u64,
+    pub kind: ChangeKind,
+    pub project: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    /// Variable or schema keys that changed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<String>,
+    /// The project's webhooks, set only for `project.deleted` since they
+    /// can't be looked up once the project is gone
+    #[serde(skip)]
+    pub webhooks: Vec<Webhook>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateEnvironmentRequest {
+    pub description: Option<String>,
+    pub parent: Option<String>,
+    pub protected: Option<bool>,
+    /// Copy variables and settings from this environment
+    pub clone_from: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateEnvironmentRequest {
+    /// Renames the environment
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub protected: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetParentRequest {
+    pub parent: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    /// `project/env`
+    pub left: String,
+    /// `project/env`
+    pub right: String,
+    #[serde(default)]
+    pub secrets: SecretDisplay,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ExportQuery {
     pub env: Option<String>,
     pub format: Option<String>,
-}
\ No newline at end of file
+    /// Remaining parameters are passed to the exporter as options
+    #[serde(flatten)]
+    pub options: std::collections::BTreeMap<String, String>,
+}
@@ -0,0 +1,205 @@
+use super::{EnvVariable, Environment, VariableType};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Per-project constraints, keyed by variable name.
+pub type Schema = BTreeMap<String, SchemaRule>;
+
+/// Constraints on one variable. Every field is optional; an empty rule only
+/// documents that the key exists.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchemaRule {
+    #[serde(rename = "type")]
+    pub value_type: VariableType,
+    /// Regular expression the whole value must match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// The only values accepted, if non-empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed: Vec<String>,
+    /// Environments the key must be set in; `*` means all of them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub required_in: Vec<String>,
+    /// Value used when the key isn't set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+/// A key that doesn't satisfy its rule.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub key: String,
+    pub message: String,
+}
+
+impl SchemaRule {
+    /// Checks the rule itself: the pattern must compile and the default must
+    /// satisfy the rest of the rule.
+    pub fn validate(&self) -> Result<(), String> {
+        self.pattern()?;
+        if let Some(default) = &self.default {
+            self.check(default)
+                .map_err(|reason| format!("default: {}", reason))?;
+        }
+        Ok(())
+    }
+
+    /// Checks `value` against the type, pattern and allowed values.
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        self.value_type.validate(value)?;
+        if let Some(pattern) = self.pattern()?
+            && !pattern.is_match(value)
+        {
+            return Err(format!(
+                "{:?} does not match /{}/",
+                value,
+                self.pattern.as_deref().unwrap_or_default()
+            ));
+        }
+        if !self.allowed.is_empty() && !self.allowed.iter().any(|allowed| allowed == value) {
+            return Err(format!(
+                "{:?} is not one of {}",
+                value,
+                self.allowed.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn is_required_in(&self, env: &str) -> bool {
+        self.required_in
+            .iter()
+            .any(|required| required == "*" || required == env)
+    }
+
+    fn default_variable(&self, default: &str) -> EnvVariable {
+        let mut var = EnvVariable::new(default.to_string(), false);
+        var.value_type = self.value_type;
+        var
+    }
+
+    fn pattern(&self) -> Result<Option<Regex>, String> {
+        self.pattern
+            .as_deref()
+            .map(|pattern| {
+                // Anchored so `[0-9]+` can't match part of `80a`
+                Regex::new(&format!("^(?:{})$", pattern))
+                    .map_err(|e| format!("invalid pattern: {}", e))
+            })
+            .transpose()
+    }
+}
+
+/// Every key in `environment` (named `env`) that breaks its rule, plus
+/// required keys that are neither set nor defaulted.
+pub fn violations(schema: &Schema, env: &str, environment: &Environment) -> Vec<Violation> {
+    schema
+        .iter()
+        .filter_map(|(key, rule)| {
+            let message = match environment.get(key) {
+                Some(var) => rule.check(&var.value).err()?,
+                None if rule.is_required_in(env) && rule.default.is_none() => {
+                    format!("required in {} but not set", env)
+                }
+                None => return None,
+            };
+            Some(Violation {
+                key: key.clone(),
+                message,
+            })
+        })
+        .collect()
+}
+
+/// Adds the schema default for every key `environment` doesn't set.
+pub fn apply_defaults(schema: &Schema, environment: &mut Environment) {
+    for (key, rule) in schema {
+        if let Some(default) = &rule.default
+            && !environment.contains_key(key)
+        {
+            environment.insert(key.clone(), rule.default_variable(default));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Schema {
+        [
+            (
+                "PORT".to_string(),
+                SchemaRule {
+                    value_type: VariableType::Port,
+                    required_in: vec!["production".to_string()],
+                    ..Default::default()
+                },
+            ),
+            (
+                "LOG_LEVEL".to_string(),
+                SchemaRule {
+                    allowed: vec!["debug".to_string(), "info".to_string()],
+                    default: Some("info".to_string()),
+                    required_in: vec!["*".to_string()],
+                    ..Default::default()
+                },
+            ),
+            (
+                "REGION".to_string(),
+                SchemaRule {
+                    pattern: Some("[a-z]+-[0-9]".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ]
+        .into()
+    }
+
+    fn environment(vars: &[(&str, &str)]) -> Environment {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), EnvVariable::new(v.to_string(), false)))
+            .collect()
+    }
+
+    #[test]
+    fn reports_missing_and_invalid_keys() {
+        let env = environment(&[("LOG_LEVEL", "trace"), ("REGION", "eu-west-1")]);
+        let keys: Vec<String> = violations(&schema(), "production", &env)
+            .into_iter()
+            .map(|violation| violation.key)
+            .collect();
+        assert_eq!(keys, ["LOG_LEVEL", "PORT", "REGION"]);
+
+        let env = environment(&[("REGION", "eu-1")]);
+        assert!(violations(&schema(), "staging", &env).is_empty());
+    }
+
+    #[test]
+    fn defaults_fill_unset_keys() {
+        let mut env = environment(&[]);
+        apply_defaults(&schema(), &mut env);
+        assert_eq!(env["LOG_LEVEL"].value, "info");
+        assert_eq!(env.len(), 1);
+    }
+
+    #[test]
+    fn rules_with_bad_defaults_are_rejected() {
+        let rule = SchemaRule {
+            value_type: VariableType::Int,
+            default: Some("ten".to_string()),
+            ..Default::default()
+        };
+        assert!(rule.validate().is_err());
+        assert!(
+            SchemaRule {
+                pattern: Some("(".to_string()),
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+}
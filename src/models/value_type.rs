@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// What a variable's string value must parse as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableType {
+    #[default]
+    String,
+    /// A signed 64-bit integer
+    Int,
+    /// `true` or `false`
+    Bool,
+    /// An absolute URL such as `https://example.com`
+    Url,
+    /// A TCP/UDP port, 1-65535
+    Port,
+    /// Any JSON document
+    Json,
+    /// A duration such as `30s`, `5m` or `1h30m`
+    Duration,
+}
+
+impl VariableType {
+    pub fn is_string(self) -> bool {
+        self == Self::String
+    }
+
+    /// Checks that `value` parses as this type, describing what was expected
+    /// if it doesn't.
+    pub fn validate(self, value: &str) -> Result<(), String> {
+        let valid = match self {
+            Self::String => true,
+            Self::Int => value.parse::<i64>().is_ok(),
+            Self::Bool => matches!(value, "true" | "false"),
+            Self::Url => is_url(value),
+            Self::Port => value.parse::<u16>().is_ok_and(|port| port != 0),
+            Self::Json => serde_json::from_str::<serde_json::Value>(value).is_ok(),
+            Self::Duration => is_duration(value),
+        };
+        if valid {
+            return Ok(());
+        }
+
+        let expected = match self {
+            Self::String => unreachable!(),
+            Self::Int => "an integer",
+            Self::Bool => "true or false",
+            Self::Url => "a URL such as https://example.com",
+            Self::Port => "a port between 1 and 65535",
+            Self::Json => "a JSON document",
+            Self::Duration => "a duration such as 30s, 5m or 1h30m",
+        };
+        Err(format!("expected {}, got {:?}", expected, value))
+    }
+
+    /// `value` as a JSON value of this type, falling back to a string for
+    /// values that don't parse.
+    pub fn to_json(self, value: &str) -> serde_json::Value {
+        let typed = match self {
+            Self::Int | Self::Port => value.parse::<i64>().ok().map(Into::into),
+            Self::Bool => value.parse::<bool>().ok().map(Into::into),
+            Self::Json => serde_json::from_str(value).ok(),
+            Self::String | Self::Url | Self::Duration => None,
+        };
+        typed.unwrap_or_else(|| value.into())
+    }
+}
+
+impl fmt::Display for VariableType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::String => "string",
+            Self::Int => "int",
+            Self::Bool => "bool",
+            Self::Url => "url",
+            Self::Port => "port",
+            Self::Json => "json",
+            Self::Duration => "duration",
+        };
+        f.write_str(name)
+    }
+}
+
+/// `scheme://host...` with an RFC 3986 scheme and a non-empty host.
+fn is_url(value: &str) -> bool {
+    let Some((scheme, rest)) = value.split_once("://") else {
+        return false;
+    };
+    let scheme_valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    scheme_valid && !host.is_empty() && !value.chars().any(char::is_whitespace)
+}
+
+/// One or more `<digits><unit>` parts, with units `ms`, `s`, `m`, `h`, `d`.
+fn is_duration(value: &str) -> bool {
+    parse_duration(value).is_some()
+}
+
+/// Parses a duration such as `30s` or `1h30m`, see [`VariableType::Duration`].
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    const UNITS: [(&str, u64); 5] = [
+        ("ms", 1),
+        ("s", 1_000),
+        ("m", 60_000),
+        ("h", 3_600_000),
+        ("d", 86_400_000),
+    ];
+
+    let mut rest = value;
+    if rest.is_empty() {
+        return None;
+    }
+    let mut millis: u64 = 0;
+    while !rest.is_empty() {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return None;
+        }
+        let amount: u64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let (unit, scale) = UNITS.into_iter().find(|(unit, _)| rest.starts_with(unit))?;
+        rest = &rest[unit.len()..];
+        millis = millis.checked_add(amount.checked_mul(scale)?)?;
+    }
+    Some(Duration::from_millis(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_each_type() {
+        let cases = [
+            (VariableType::Int, "-42", "4.2"),
+            (VariableType::Bool, "false", "yes"),
+            (VariableType::Url, "postgres://user@db:5432/app", "db:5432"),
+            (VariableType::Port, "8080", "80800"),
+            (VariableType::Json, r#"{"a": [1]}"#, "{a: 1}"),
+            (VariableType::Duration, "1h30m", "90"),
+        ];
+        for (kind, valid, invalid) in cases {
+            assert!(kind.validate(valid).is_ok(), "{} {}", kind, valid);
+            assert!(kind.validate(invalid).is_err(), "{} {}", kind, invalid);
+        }
+        assert!(VariableType::Port.validate("0").is_err());
+        assert!(VariableType::Duration.validate("250ms").is_ok());
+    }
+
+    #[test]
+    fn durations_parse() {
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("30"), None);
+    }
+
+    #[test]
+    fn json_values_are_typed() {
+        assert_eq!(VariableType::Port.to_json("8080"), serde_json::json!(8080));
+        assert_eq!(VariableType::Bool.to_json("true"), serde_json::json!(true));
+        assert_eq!(
+            VariableType::Json.to_json("[1, 2]"),
+            serde_json::json!([1, 2])
+        );
+        assert_eq!(
+            VariableType::Duration.to_json("5m"),
+            serde_json::json!("5m")
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use super::{ChangeEvent, ChangeKind, VariableType};
+use serde::{Deserialize, Serialize};
+
+/// A URL that is sent a signed JSON payload when the project changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    /// Key for the `X-Rusty-Signature` HMAC
+    pub secret: String,
+    /// Event kinds to send, e.g. `variables.changed`; empty means all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    /// Environments to send events for; empty means all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub environments: Vec<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A webhook as listed by the API, without its secret. The secret is only
+/// returned when the webhook is created.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookInfo {
+    pub id: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub environments: Vec<String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<Webhook> for WebhookInfo {
+    fn from(webhook: Webhook) -> Self {
+        Self {
+            id: webhook.id,
+            url: webhook.url,
+            events: webhook.events,
+            environments: webhook.environments,
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+/// A project's webhooks and delivery log. Kept beside the project rather
+/// than in it, so neither is part of project responses.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectWebhooks {
+    pub webhooks: Vec<Webhook>,
+    /// Most recent deliveries, oldest first
+    pub deliveries: Vec<Delivery>,
+}
+
+/// The outcome of sending one event to one webhook, after any retries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: String,
+    pub webhook: String,
+    pub event: String,
+    pub revision: u64,
+    pub attempts: u32,
+    /// HTTP status of the last attempt, if the request got a response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub success: bool,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub delivered_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Webhook {
+    /// A webhook with a random secret unless one is given. Checks the URL
+    /// and event names.
+    pub fn new(
+        url: String,
+        secret: Option<String>,
+        events: Vec<String>,
+        environments: Vec<String>,
+    ) -> Result<Self, String> {
+        VariableType::Url.validate(&url)?;
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!("expected an http or https URL, got {:?}", url));
+        }
+        if let Some(unknown) = events
+            .iter()
+            .find(|event| !ChangeKind::ALL.iter().any(|kind| kind.name() == *event))
+        {
+            let known: Vec<&str> = ChangeKind::ALL.iter().map(|kind| kind.name()).collect();
+            return Err(format!(
+                "unknown event {:?}, expected one of {}",
+                unknown,
+                known.join(", ")
+            ));
+        }
+
+        let random = || uuid::Uuid::new_v4().simple().to_string();
+        Ok(Self {
+            id: random()[..12].to_string(),
+            url,
+            secret: secret.unwrap_or_else(|| random() + &random()),
+            events,
+            environments,
+            created_at: chrono::Utc::now(),
+        })
+    }
+
+    /// Whether `event` passes the event and environment filters.
+    pub fn wants(&self, event: &ChangeEvent) -> bool {
+        let kind_matches =
+            self.events.is_empty() || self.events.iter().any(|kind| kind == event.kind.name());
+        let env_matches = self.environments.is_empty()
+            || event
+                .environment
+                .as_ref()
+                .is_some_and(|env| self.environments.contains(env));
+        kind_matches && env_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: ChangeKind, environment: Option<&str>) -> ChangeEvent {
+        ChangeEvent {
+            revision: 1,
+            kind,
+            project: "app".to_string(),
+            environment: environment.map(str::to_string),
+            keys: Vec::new(),
+            webhooks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn filters_by_event_and_environment() {
+        let hook = Webhook::new(
+            "https://hooks.example.com/x".to_string(),
+            None,
+            vec!["variables.changed".to_string()],
+            vec!["production".to_string()],
+        )
+        .unwrap();
+        assert!(hook.wants(&event(ChangeKind::VariablesChanged, Some("production"))));
+        assert!(!hook.wants(&event(ChangeKind::VariablesChanged, Some("staging"))));
+        assert!(!hook.wants(&event(ChangeKind::SchemaChanged, None)));
+        assert_eq!(hook.secret.len(), 64);
+    }
+
+    #[test]
+    fn rejects_bad_urls_and_events() {
+        let new = |url: &str, event: &str| {
+            Webhook::new(url.to_string(), None, vec![event.to_string()], Vec::new())
+        };
+        assert!(new("ftp://example.com", "project.created").is_err());
+        assert!(new("https://example.com", "variable.changed").is_err());
+        assert!(new("https://example.com", "project.created").is_ok());
+    }
+}
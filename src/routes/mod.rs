@@ -1,15 +1,40 @@
-use crate::db::JsonStore;
+use crate::config::DefaultsConfig;
+use crate::db::{JsonStore, Precondition, Target};
+use crate::diff;
 use crate::error::{AppError, Result};
-use crate::models::{CreateProjectRequest, ExportQuery, SetVariableRequest, UpdateProjectRequest};
+use crate::formats::{self, ExportContext};
+use crate::models::{
+    ChangeEvent, ChangeKind, CreateEnvironmentRequest, CreateProjectRequest, CreateWebhookRequest,
+    DiffQuery, EnvironmentPatch, EnvironmentQuery, EnvironmentSettings, EventsQuery, ExportQuery,
+    ImportOptions, ImportQuery, SchemaRule, SetParentRequest, SetVariableRequest,
+    UpdateEnvironmentRequest, UpdateProjectRequest, Webhook, WebhookInfo, parse_duration,
+};
+use crate::webhook;
 use axum::{
     Json, Router,
-    extract::{Path, Query, State},
-    http::StatusCode,
-    routing::get,
+    extract::{FromRef, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, patch, post, put},
 };
 use serde_json::{Value, json};
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub store: JsonStore,
+    pub defaults: DefaultsConfig,
+}
+
+impl FromRef<AppState> for JsonStore {
+    fn from_ref(state: &AppState) -> Self {
+        state.store.clone()
+    }
+}
 
-pub fn create_router(store: JsonStore) -> Router {
+pub fn create_router(store: JsonStore, defaults: DefaultsConfig) -> Router {
     Router::new()
         // Project routes
         .route("/api/projects", get(list_projects).post(create_project))
@@ -19,16 +44,99 @@ pub fn create_router(store: JsonStore) -> Router {
         )
         // Environment routes
         .route("/api/projects/{name}/envs", get(list_environments))
-        .route("/api/projects/{name}/envs/{env}", get(get_environment))
+        .route(
+            "/api/projects/{name}/envs/{env}",
+            get(get_environment)
+                .post(create_environment)
+                .patch(patch_environment)
+                .delete(remove_environment),
+        )
+        .route(
+            "/api/projects/{name}/envs/{env}/settings",
+            patch(update_environment),
+        )
         .route(
             "/api/projects/{name}/envs/{env}/vars/{key}",
             get(get_variable).put(set_variable).delete(delete_variable),
         )
-        // Export route
+        .route(
+            "/api/projects/{name}/envs/{env}/import",
+            post(import_environment),
+        )
+        .route("/api/projects/{name}/envs/{env}/parent", put(set_parent))
+        .route(
+            "/api/projects/{name}/envs/{env}/validate",
+            get(validate_environment),
+        )
+        // Schema routes
+        .route("/api/projects/{name}/schema", get(get_schema))
+        .route(
+            "/api/projects/{name}/schema/{key}",
+            put(set_schema_rule).delete(remove_schema_rule),
+        )
+        // Export routes
+        .route(
+            "/api/projects/{name}/webhooks",
+            get(list_webhooks).post(create_webhook),
+        )
+        .route(
+            "/api/projects/{name}/webhooks/{id}",
+            axum::routing::delete(delete_webhook),
+        )
+        .route(
+            "/api/projects/{name}/webhooks/{id}/test",
+            post(test_webhook),
+        )
+        .route("/api/projects/{name}/deliveries", get(list_deliveries))
         .route("/api/projects/{name}/export", get(export_project))
-        .with_state(store)
+        .route("/api/formats", get(list_formats))
+        .route("/api/diff", get(diff_environments))
+        .route("/api/events", get(stream_events))
+        .with_state(AppState { store, defaults })
 }
 
+/// `ETag: "<revision>"`, or no header for something without a revision.
+fn etag(revision: Option<u64>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(revision) = revision {
+        let value = HeaderValue::from_str(&format!("\"{}\"", revision))
+            .expect("a quoted number is a valid header value");
+        headers.insert(header::ETAG, value);
+    }
+    headers
+}
+
+/// The store, guarded by the request's `If-Match` header if it has one.
+/// Tags that aren't one of ours (weak or malformed) never match.
+fn if_match(store: &JsonStore, headers: &HeaderMap, target: Target) -> JsonStore {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return store.clone();
+    };
+    let value = value.to_str().unwrap_or_default().trim();
+    let revisions = (value != "*").then(|| {
+        value
+            .split(',')
+            .filter_map(|tag| {
+                tag.trim()
+                    .strip_prefix('"')?
+                    .strip_suffix('"')?
+                    .parse()
+                    .ok()
+            })
+            .collect()
+    });
+    store.with_precondition(Precondition { target, revisions })
+}
+
+/// Answers blocking queries when nothing changes for this long
+const DEFAULT_WAIT: Duration = Duration::from_secs(5 * 60);
+const MAX_WAIT: Duration = Duration::from_secs(10 * 60);
+/// The watch index of a blocking query's answer, to pass back as `index`
+const INDEX_HEADER: &str = "x-rusty-index";
+/// Fields of `PATCH .../envs/{env}/settings`, which that route took before
+/// it became a patch of the variables
+const SETTINGS_FIELDS: &[&str] = &["name", "description", "protected", "parent"];
+
 // Project handlers
 #[axum::debug_handler]
 async fn create_project(
@@ -43,9 +151,9 @@ async fn create_project(
 async fn get_project(
     State(store): State<JsonStore>,
     Path(name): Path<String>,
-) -> Result<Json<Value>> {
+) -> Result<(HeaderMap, Json<Value>)> {
     let project = store.get_project(&name).await?;
-    Ok(Json(json!(project)))
+    Ok((etag(Some(project.revision)), Json(json!(project))))
 }
 
 #[axum::debug_handler]
@@ -58,20 +166,24 @@ async fn list_projects(State(store): State<JsonStore>) -> Result<Json<Value>> {
 async fn update_project(
     State(store): State<JsonStore>,
     Path(name): Path<String>,
+    headers: HeaderMap,
     Json(req): Json<UpdateProjectRequest>,
-) -> Result<Json<Value>> {
-    let project = store
+) -> Result<(HeaderMap, Json<Value>)> {
+    let project = if_match(&store, &headers, Target::Project(name.clone()))
         .update_project(&name, req.name, req.description)
         .await?;
-    Ok(Json(json!(project)))
+    Ok((etag(Some(project.revision)), Json(json!(project))))
 }
 
 #[axum::debug_handler]
 async fn delete_project(
     State(store): State<JsonStore>,
     Path(name): Path<String>,
+    headers: HeaderMap,
 ) -> Result<StatusCode> {
-    store.delete_project(&name).await?;
+    if_match(&store, &headers, Target::Project(name.clone()))
+        .delete_project(&name)
+        .await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -79,44 +191,182 @@ async fn delete_project(
 async fn set_variable(
     State(store): State<JsonStore>,
     Path((project_name, env, key)): Path<(String, String, String)>,
+    headers: HeaderMap,
     Json(req): Json<SetVariableRequest>,
-) -> Result<(StatusCode, Json<Value>)> {
-    let variable = store
+) -> Result<(StatusCode, HeaderMap, Json<Value>)> {
+    let target = Target::Variable(project_name.clone(), env.clone(), key.clone());
+    let variable = if_match(&store, &headers, target)
         .set_variable(
             &project_name,
             &env,
             key,
             req.value,
             req.encrypted.unwrap_or(false),
+            req.value_type,
         )
         .await?;
-    Ok((StatusCode::CREATED, Json(json!(variable))))
+    Ok((
+        StatusCode::CREATED,
+        etag(Some(variable.revision)),
+        Json(json!(variable)),
+    ))
 }
 
 async fn get_variable(
     State(store): State<JsonStore>,
     Path((project_name, env, key)): Path<(String, String, String)>,
-) -> Result<Json<Value>> {
+) -> Result<(HeaderMap, Json<Value>)> {
+    // Inherited and default values have no revision in this environment
+    let target = Target::Variable(project_name.clone(), env.clone(), key.clone());
+    let revision = store.revision(&target).await.ok();
     let variable = store.get_variable(&project_name, &env, &key).await?;
-    Ok(Json(json!(variable)))
+    Ok((etag(revision), Json(json!(variable))))
 }
 
 async fn delete_variable(
     State(store): State<JsonStore>,
     Path((project_name, env, key)): Path<(String, String, String)>,
+    headers: HeaderMap,
 ) -> Result<StatusCode> {
-    store.delete_variable(&project_name, &env, &key).await?;
+    let target = Target::Variable(project_name.clone(), env.clone(), key.clone());
+    if_match(&store, &headers, target)
+        .delete_variable(&project_name, &env, &key)
+        .await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn get_environment(
     State(store): State<JsonStore>,
     Path((project_name, env)): Path<(String, String)>,
+    Query(params): Query<EnvironmentQuery>,
+) -> Result<(HeaderMap, Json<Value>)> {
+    // A blocking query, as in Consul: with `index`, answer once the
+    // environment has changed past it or `wait` runs out
+    let index = match params.index {
+        Some(index) => {
+            let wait = match &params.wait {
+                Some(wait) => parse_duration(wait).ok_or_else(|| {
+                    AppError::InvalidInput(format!("invalid wait duration: {}", wait))
+                })?,
+                None => DEFAULT_WAIT,
+            };
+            store
+                .wait_for_change(&project_name, &env, index, wait.min(MAX_WAIT))
+                .await?
+        }
+        None => store.watch_index(&project_name, &env).await?,
+    };
+
+    // Read first, so the ETag is never newer than the body
+    let revision = store
+        .revision(&Target::Environment(project_name.clone(), env.clone()))
+        .await?;
+    let mut headers = etag(Some(revision));
+    headers.insert(INDEX_HEADER, HeaderValue::from(index));
+
+    if params.explain {
+        let explained: serde_json::Map<String, Value> = store
+            .explain_environment(&project_name, &env)
+            .await?
+            .into_iter()
+            .map(|(key, var, source)| {
+                let mut value = json!(var);
+                value["source"] = json!(source);
+                (key, value)
+            })
+            .collect();
+        return Ok((headers, Json(Value::Object(explained))));
+    }
+
+    let environment = if params.raw {
+        store.get_raw_environment(&project_name, &env).await?
+    } else {
+        store.get_environment(&project_name, &env).await?
+    };
+    Ok((headers, Json(json!(environment))))
+}
+
+async fn create_environment(
+    State(store): State<JsonStore>,
+    Path((project_name, env)): Path<(String, String)>,
+    Json(req): Json<CreateEnvironmentRequest>,
+) -> Result<(StatusCode, Json<Value>)> {
+    let settings = EnvironmentSettings {
+        parent: req.parent,
+        description: req.description,
+        protected: req.protected.unwrap_or(false),
+        ..Default::default()
+    };
+    let settings = store
+        .create_environment(&project_name, &env, settings, req.clone_from.as_deref())
+        .await?;
+    Ok((StatusCode::CREATED, Json(json!(settings))))
+}
+
+/// Merges `patch` into the environment's variables. A body naming a
+/// settings field is refused, as a settings update sent to the old route
+/// would otherwise create variables; such a variable is set with
+/// `PUT .../vars/{key}`.
+async fn patch_environment(
+    State(store): State<JsonStore>,
+    Path((project_name, env)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
 ) -> Result<Json<Value>> {
-    let environment = store.get_environment(&project_name, &env).await?;
+    // Checked before parsing, as settings values aren't valid variable patches
+    if let Some(field) = body
+        .as_object()
+        .into_iter()
+        .flat_map(|object| object.keys())
+        .find(|key| SETTINGS_FIELDS.contains(&key.as_str()))
+    {
+        return Err(AppError::InvalidInput(format!(
+            "{} is an environment setting; update settings with PATCH \
+             /api/projects/{}/envs/{}/settings, or set a variable of that name with PUT \
+             /api/projects/{}/envs/{}/vars/{}",
+            field, project_name, env, project_name, env, field
+        )));
+    }
+    let patch: EnvironmentPatch = serde_json::from_value(body)
+        .map_err(|e| AppError::InvalidInput(format!("invalid patch: {}", e)))?;
+    let target = Target::Environment(project_name.clone(), env.clone());
+    let environment = if_match(&store, &headers, target)
+        .patch_environment(&project_name, &env, patch)
+        .await?;
     Ok(Json(json!(environment)))
 }
 
+async fn update_environment(
+    State(store): State<JsonStore>,
+    Path((project_name, env)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(req): Json<UpdateEnvironmentRequest>,
+) -> Result<Json<Value>> {
+    let target = Target::Environment(project_name.clone(), env.clone());
+    let settings = if_match(&store, &headers, target)
+        .update_environment(
+            &project_name,
+            &env,
+            req.name,
+            req.description,
+            req.protected,
+        )
+        .await?;
+    Ok(Json(json!(settings)))
+}
+
+async fn remove_environment(
+    State(store): State<JsonStore>,
+    Path((project_name, env)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<StatusCode> {
+    let target = Target::Environment(project_name.clone(), env.clone());
+    if_match(&store, &headers, target)
+        .remove_environment(&project_name, &env)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
 async fn list_environments(
     State(store): State<JsonStore>,
     Path(project_name): Path<String>,
@@ -125,64 +375,226 @@ async fn list_environments(
     Ok(Json(json!(environments)))
 }
 
-async fn export_project(
+async fn import_environment(
     State(store): State<JsonStore>,
-    Path(project_name): Path<String>,
-    Query(params): Query<ExportQuery>,
-) -> Result<String> {
-    let env = params.env.unwrap_or_else(|| "development".to_string());
+    Path((project_name, env)): Path<(String, String)>,
+    Query(params): Query<ImportQuery>,
+    body: String,
+) -> Result<Json<Value>> {
     let format = params.format.unwrap_or_else(|| "dotenv".to_string());
+    let ctx = formats::ImportContext {
+        options: params
+            .separator
+            .map(|separator| ("separator".to_string(), separator))
+            .into_iter()
+            .collect(),
+    };
+    let vars = formats::registry().import(&format, &body, &ctx)?;
 
-    let environment = store.get_environment(&project_name, &env).await?;
-
-    let output = match format.as_str() {
-        "dotenv" => export_dotenv(&environment),
-        "json" => export_json(&environment)?,
-        "yaml" => export_yaml(&environment),
-        "docker" => export_docker(&environment),
-        _ => {
-            return Err(AppError::InvalidInput(format!(
-                "Unknown format: {}",
-                format
-            )));
-        }
+    let options = ImportOptions {
+        strategy: params.strategy.unwrap_or_default(),
+        encrypted: params.encrypted.unwrap_or(false),
+        dry_run: params.dry_run.unwrap_or(false),
     };
+    let report = store
+        .import_variables(&project_name, &env, vars, options)
+        .await?;
+    Ok(Json(json!(report)))
+}
 
-    Ok(output)
+async fn set_parent(
+    State(store): State<JsonStore>,
+    Path((project_name, env)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(req): Json<SetParentRequest>,
+) -> Result<StatusCode> {
+    let target = Target::Environment(project_name.clone(), env.clone());
+    if_match(&store, &headers, target)
+        .set_parent(&project_name, &env, req.parent)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-// Export format helpers
-pub fn export_dotenv(
-    env: &std::collections::HashMap<String, crate::models::EnvVariable>,
-) -> String {
-    env.iter()
-        .map(|(key, var)| format!("{}={}", key, var.value))
-        .collect::<Vec<_>>()
-        .join("\n")
+async fn validate_environment(
+    State(store): State<JsonStore>,
+    Path((project_name, env)): Path<(String, String)>,
+) -> Result<Json<Value>> {
+    let violations = store.validate_environment(&project_name, &env).await?;
+    Ok(Json(json!({
+        "valid": violations.is_empty(),
+        "violations": violations,
+    })))
 }
 
-pub fn export_json(
-    env: &std::collections::HashMap<String, crate::models::EnvVariable>,
-) -> Result<String> {
-    let map: std::collections::HashMap<&str, &str> = env
-        .iter()
-        .map(|(k, v)| (k.as_str(), v.value.as_str()))
+// Schema handlers
+async fn get_schema(
+    State(store): State<JsonStore>,
+    Path(project_name): Path<String>,
+) -> Result<(HeaderMap, Json<Value>)> {
+    let project = store.get_project(&project_name).await?;
+    Ok((etag(Some(project.revision)), Json(json!(project.schema))))
+}
+
+async fn set_schema_rule(
+    State(store): State<JsonStore>,
+    Path((project_name, key)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(rule): Json<SchemaRule>,
+) -> Result<Json<Value>> {
+    let rule = if_match(&store, &headers, Target::Project(project_name.clone()))
+        .set_schema_rule(&project_name, key, rule)
+        .await?;
+    Ok(Json(json!(rule)))
+}
+
+async fn remove_schema_rule(
+    State(store): State<JsonStore>,
+    Path((project_name, key)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<StatusCode> {
+    if_match(&store, &headers, Target::Project(project_name.clone()))
+        .remove_schema_rule(&project_name, &key)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_webhooks(
+    State(store): State<JsonStore>,
+    Path(project_name): Path<String>,
+) -> Result<Json<Value>> {
+    let webhooks: Vec<WebhookInfo> = store
+        .list_webhooks(&project_name)
+        .await?
+        .into_iter()
+        .map(WebhookInfo::from)
         .collect();
-    serde_json::to_string_pretty(&map).map_err(Into::into)
+    Ok(Json(json!(webhooks)))
+}
+
+async fn create_webhook(
+    State(store): State<JsonStore>,
+    Path(project_name): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<Value>)> {
+    let webhook = Webhook::new(req.url, req.secret, req.events, req.environments)
+        .map_err(AppError::InvalidInput)?;
+    let webhook = if_match(&store, &headers, Target::Project(project_name.clone()))
+        .add_webhook(&project_name, webhook)
+        .await?;
+    Ok((StatusCode::CREATED, Json(json!(webhook))))
 }
 
-pub fn export_yaml(env: &std::collections::HashMap<String, crate::models::EnvVariable>) -> String {
-    env.iter()
-        .map(|(key, var)| format!("{}: {}", key, var.value))
-        .collect::<Vec<_>>()
-        .join("\n")
+async fn delete_webhook(
+    State(store): State<JsonStore>,
+    Path((project_name, id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<StatusCode> {
+    if_match(&store, &headers, Target::Project(project_name.clone()))
+        .remove_webhook(&project_name, &id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub fn export_docker(
-    env: &std::collections::HashMap<String, crate::models::EnvVariable>,
-) -> String {
-    env.iter()
-        .map(|(key, var)| format!("-e {}={}", key, var.value))
-        .collect::<Vec<_>>()
-        .join(" ")
+/// Sends a single ping attempt and returns its delivery record.
+async fn test_webhook(
+    State(store): State<JsonStore>,
+    Path((project_name, id)): Path<(String, String)>,
+) -> Result<Json<Value>> {
+    let webhook = store.get_webhook(&project_name, &id).await?;
+    let ping = ChangeEvent {
+        revision: store
+            .revision(&Target::Project(project_name.clone()))
+            .await?,
+        kind: ChangeKind::Ping,
+        project: project_name,
+        environment: None,
+        keys: Vec::new(),
+        webhooks: Vec::new(),
+    };
+    let delivery = webhook::Sender::new(store.clone(), webhook::Policy::TEST)
+        .deliver(&webhook, &ping)
+        .await;
+    store
+        .record_deliveries(vec![(ping.project, delivery.clone())])
+        .await?;
+    Ok(Json(json!(delivery)))
+}
+
+/// The delivery log, newest first.
+async fn list_deliveries(
+    State(store): State<JsonStore>,
+    Path(project_name): Path<String>,
+) -> Result<Json<Value>> {
+    let mut deliveries = store.list_deliveries(&project_name).await?;
+    deliveries.reverse();
+    Ok(Json(json!(deliveries)))
+}
+
+async fn diff_environments(
+    State(store): State<JsonStore>,
+    Query(params): Query<DiffQuery>,
+) -> Result<Json<Value>> {
+    let mut sides = Vec::new();
+    for side in [&params.left, &params.right] {
+        let (project, env) = side
+            .split_once('/')
+            .ok_or_else(|| AppError::InvalidInput(format!("expected project/env, got {}", side)))?;
+        sides.push(store.get_environment(project, env).await?);
+    }
+
+    let entries = diff::diff(&sides[0], &sides[1], params.secrets);
+    Ok(Json(json!({
+        "left": params.left,
+        "right": params.right,
+        "differences": entries,
+    })))
+}
+
+/// Server-Sent Events for every change saved through the store, named by
+/// their kind, e.g. `event: variables.changed`.
+async fn stream_events(
+    State(store): State<JsonStore>,
+    Query(params): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let events = BroadcastStream::new(store.subscribe()).filter_map(move |event| {
+        // A subscriber that falls too far behind skips what it missed
+        let event = event.ok()?;
+        if params
+            .project
+            .as_ref()
+            .is_some_and(|project| *project != event.project)
+        {
+            return None;
+        }
+        Some(Ok(Event::default()
+            .event(event.kind.name())
+            .id(event.revision.to_string())
+            .json_data(&event)
+            .unwrap_or_default()))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+async fn list_formats() -> Json<Value> {
+    Json(json!(formats::registry().formats()))
+}
+
+async fn export_project(
+    State(state): State<AppState>,
+    Path(project_name): Path<String>,
+    Query(params): Query<ExportQuery>,
+) -> Result<String> {
+    let AppState { store, defaults } = state;
+    let env = params.env.unwrap_or(defaults.environment);
+    let format = params.format.unwrap_or(defaults.export_format);
+
+    let environment = store.get_environment(&project_name, &env).await?;
+
+    let ctx = ExportContext {
+        project: project_name,
+        environment: env,
+        options: params.options,
+    };
+    formats::registry().export(&format, &environment, &ctx)
 }
@@ -0,0 +1,295 @@
+use crate::error::{AppError, Result};
+use crate::models::Environment;
+use std::process::{Command, ExitStatus, Stdio};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const REDACTED: &[u8] = b"***";
+
+/// Builds a command whose environment is the caller's merged with `environment`.
+///
+/// When `override_existing` is false, variables already present in the
+/// calling process keep their value.
+pub fn command(
+    environment: &Environment,
+    argv: &[String],
+    override_existing: bool,
+) -> Result<Command> {
+    let (program, args) = argv
+        .split_first()
+        .ok_or_else(|| AppError::InvalidInput("No command given".to_string()))?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+
+    for (key, var) in environment {
+        if override_existing || std::env::var_os(key).is_none() {
+            cmd.env(key, &var.value);
+        }
+    }
+
+    Ok(cmd)
+}
+
+/// Replaces the current process with `cmd`, so signals and the exit status
+/// reach the caller unchanged. Only returns on failure to start.
+#[cfg(unix)]
+pub fn exec(mut cmd: Command) -> Result<i32> {
+    use std::os::unix::process::CommandExt;
+
+    Err(AppError::IoError(cmd.exec()))
+}
+
+/// Runs `cmd` to completion and returns its exit code.
+#[cfg(not(unix))]
+pub fn exec(mut cmd: Command) -> Result<i32> {
+    let status = cmd.status()?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Masks the values of encrypted variables in process output.
+pub struct Redactor {
+    secrets: Vec<Vec<u8>>,
+}
+
+impl Redactor {
+    pub fn new(environment: &Environment) -> Self {
+        let mut secrets: Vec<Vec<u8>> = environment
+            .values()
+            .filter(|var| var.encrypted && !var.value.is_empty())
+            .map(|var| var.value.as_bytes().to_vec())
+            .collect();
+        // Longest first, so a secret containing another is masked whole
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        secrets.dedup();
+
+        Self { secrets }
+    }
+
+    pub fn redact(&self, input: &[u8]) -> Vec<u8> {
+        self.scan(input, input.len()).0
+    }
+
+    /// Redacts the start of `input` up to `end`, where a secret may begin
+    /// but run past `end`. Returns the output and how much input it covers.
+    fn scan(&self, input: &[u8], end: usize) -> (Vec<u8>, usize) {
+        let mut output = Vec::with_capacity(input.len());
+        let mut i = 0;
+
+        'scan: while i < end {
+            for secret in &self.secrets {
+                if input[i..].starts_with(secret) {
+                    output.extend_from_slice(REDACTED);
+                    i += secret.len();
+                    continue 'scan;
+                }
+            }
+            output.push(input[i]);
+            i += 1;
+        }
+
+        (output, i)
+    }
+
+    /// The longest secret, which bounds how much output must be held back.
+    fn longest(&self) -> usize {
+        self.secrets.first().map_or(0, Vec::len)
+    }
+}
+
+/// Redacts output that arrives in arbitrary chunks. Secrets can span lines
+/// and chunks, so the last `longest secret - 1` bytes are held back until
+/// more output shows whether they start one.
+pub struct RedactingBuffer<'a> {
+    redactor: &'a Redactor,
+    pending: Vec<u8>,
+}
+
+impl<'a> RedactingBuffer<'a> {
+    pub fn new(redactor: &'a Redactor) -> Self {
+        Self {
+            redactor,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Adds `chunk` and returns the output that can be released.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        let held = self.redactor.longest().saturating_sub(1);
+        let end = self.pending.len().saturating_sub(held);
+        let (output, consumed) = self.redactor.scan(&self.pending, end);
+        self.pending.drain(..consumed);
+        output
+    }
+
+    /// Releases whatever is still held back, at the end of the stream.
+    pub fn finish(self) -> Vec<u8> {
+        self.redactor.redact(&self.pending)
+    }
+}
+
+/// Runs `cmd` as a child process, passing its stdout and stderr through
+/// `redactor`, and returns its exit code. `SIGTERM` and
+/// `SIGHUP` are forwarded to the child; `SIGINT` from the terminal already
+/// reaches it through the process group.
+pub async fn spawn_redacted(cmd: Command, redactor: Redactor) -> Result<i32> {
+    let mut cmd = tokio::process::Command::from(cmd);
+    cmd.stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = cmd.spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let redactor = std::sync::Arc::new(redactor);
+    let out = tokio::spawn(copy_redacted(stdout, tokio::io::stdout(), redactor.clone()));
+    let err = tokio::spawn(copy_redacted(stderr, tokio::io::stderr(), redactor));
+
+    let status = wait_forwarding_signals(&mut child).await?;
+    out.await.map_err(std::io::Error::other)??;
+    err.await.map_err(std::io::Error::other)??;
+
+    Ok(exit_code(status))
+}
+
+async fn copy_redacted<R, W>(
+    reader: R,
+    mut writer: W,
+    redactor: std::sync::Arc<Redactor>,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut reader = reader;
+    let mut buffer = RedactingBuffer::new(&redactor);
+    let mut chunk = vec![0; 8192];
+
+    loop {
+        let read = reader.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer.push(&chunk[..read])).await?;
+        writer.flush().await?;
+    }
+    writer.write_all(&buffer.finish()).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn wait_forwarding_signals(child: &mut tokio::process::Child) -> std::io::Result<ExitStatus> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    loop {
+        let signum = tokio::select! {
+            status = child.wait() => return status,
+            _ = interrupt.recv() => continue,
+            _ = terminate.recv() => libc::SIGTERM,
+            _ = hangup.recv() => libc::SIGHUP,
+        };
+
+        if let Some(pid) = child.id() {
+            // SAFETY: `kill` has no memory-safety preconditions.
+            unsafe {
+                libc::kill(pid as libc::pid_t, signum);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_forwarding_signals(child: &mut tokio::process::Child) -> std::io::Result<ExitStatus> {
+    child.wait().await
+}
+
+/// Exit code to report for `status`, using the shell's `128 + signal`
+/// convention for children killed by a signal.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    status.code().unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EnvVariable;
+
+    fn redactor(secrets: &[&str]) -> Redactor {
+        let environment: Environment = secrets
+            .iter()
+            .enumerate()
+            .map(|(i, secret)| {
+                (
+                    format!("S{}", i),
+                    EnvVariable::new(secret.to_string(), true),
+                )
+            })
+            .collect();
+        Redactor::new(&environment)
+    }
+
+    /// Feeds `input` through a buffer `size` bytes at a time.
+    fn redact_in_chunks(redactor: &Redactor, input: &str, size: usize) -> String {
+        let mut buffer = RedactingBuffer::new(redactor);
+        let mut output = Vec::new();
+        for chunk in input.as_bytes().chunks(size) {
+            output.extend(buffer.push(chunk));
+        }
+        output.extend(buffer.finish());
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn redacts_single_line_secrets() {
+        let redactor = redactor(&["hunter2", "hunter2-admin"]);
+        assert_eq!(
+            String::from_utf8(redactor.redact(b"pw=hunter2 admin=hunter2-admin\n")).unwrap(),
+            "pw=*** admin=***\n"
+        );
+    }
+
+    #[test]
+    fn redacts_secrets_spanning_lines() {
+        let key = "-----BEGIN KEY-----\nMIIEabc\n-----END KEY-----";
+        let redactor = redactor(&[key]);
+        let output = format!("loaded:\n{}\ndone\n", key);
+        assert_eq!(
+            redact_in_chunks(&redactor, &output, 8192),
+            "loaded:\n***\ndone\n"
+        );
+    }
+
+    #[test]
+    fn redacts_secrets_split_across_reads() {
+        let redactor = redactor(&["s3cr3t-token"]);
+        let output = "a s3cr3t-token b s3cr3t-token";
+        for size in 1..output.len() {
+            assert_eq!(
+                redact_in_chunks(&redactor, output, size),
+                "a *** b ***",
+                "{}",
+                size
+            );
+        }
+        // Output that isn't a secret is only held back by the secret's length
+        let mut buffer = RedactingBuffer::new(&redactor);
+        assert_eq!(buffer.push(b"0123456789abcdef"), b"01234");
+        assert_eq!(buffer.finish(), b"56789abcdef");
+    }
+}
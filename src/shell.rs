@@ -0,0 +1,113 @@
+use clap::ValueEnum;
+use std::path::Path;
+
+/// Shells rusty can emit code for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    /// Statement that sets and exports `key` to `value`.
+    pub fn export(&self, key: &str, value: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => format!("export {}={};", key, quote_posix(value)),
+            Shell::Fish => format!("set -gx {} {};", key, quote_fish(value)),
+        }
+    }
+
+    /// Statement that removes `key` from the environment.
+    pub fn unset(&self, key: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => format!("unset {};", key),
+            Shell::Fish => format!("set -e {};", key),
+        }
+    }
+
+    /// Code to add to the shell's rc file so `exe hook-env` runs on every
+    /// directory change.
+    pub fn hook(&self, exe: &Path) -> String {
+        let exe = exe.display().to_string();
+        match self {
+            Shell::Bash => format!(
+                r#"_rusty_hook() {{
+  local previous_exit_status=$?
+  eval "$({exe} hook-env bash)"
+  return $previous_exit_status
+}}
+if [[ ";${{PROMPT_COMMAND[*]:-}};" != *";_rusty_hook;"* ]]; then
+  PROMPT_COMMAND="_rusty_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}"
+fi
+"#,
+                exe = quote_posix(&exe)
+            ),
+            Shell::Zsh => format!(
+                r#"_rusty_hook() {{
+  eval "$({exe} hook-env zsh)"
+}}
+typeset -ag precmd_functions chpwd_functions
+if (( ! ${{precmd_functions[(I)_rusty_hook]}} )); then
+  precmd_functions=(_rusty_hook $precmd_functions)
+fi
+if (( ! ${{chpwd_functions[(I)_rusty_hook]}} )); then
+  chpwd_functions=(_rusty_hook $chpwd_functions)
+fi
+"#,
+                exe = quote_posix(&exe)
+            ),
+            Shell::Fish => format!(
+                r#"function __rusty_hook --on-variable PWD --description 'Load rusty variables on directory change'
+    {exe} hook-env fish | source
+end
+__rusty_hook
+"#,
+                exe = quote_fish(&exe)
+            ),
+        }
+    }
+}
+
+/// Whether `key` can be used as a variable name in every supported shell.
+pub fn is_valid_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Single-quotes `value` for POSIX shells; embedded quotes become `'\''`.
+pub fn quote_posix(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Single-quotes `value` for fish, where only `\` and `'` need escaping.
+pub fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_values_for_each_shell() {
+        assert_eq!(quote_posix("plain"), "'plain'");
+        assert_eq!(quote_posix("it's $HOME"), r"'it'\''s $HOME'");
+        assert_eq!(quote_posix("a\nb"), "'a\nb'");
+        assert_eq!(quote_fish(r"it's C:\dir"), r"'it\'s C:\\dir'");
+        assert_eq!(Shell::Zsh.export("K", "v"), "export K='v';");
+        assert_eq!(Shell::Fish.export("K", "v"), "set -gx K 'v';");
+        assert_eq!(Shell::Fish.unset("K"), "set -e K;");
+    }
+
+    #[test]
+    fn accepts_only_portable_names() {
+        assert!(is_valid_name("_DB_URL2"));
+        assert!(!is_valid_name("2FAST"));
+        assert!(!is_valid_name("with-dash"));
+        assert!(!is_valid_name(""));
+    }
+}
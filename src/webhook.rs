@@ -0,0 +1,314 @@
+use crate::db::JsonStore;
+use crate::models::{ChangeEvent, ChangeKind, Delivery, Webhook};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::oneshot;
+use tokio::task::{JoinHandle, JoinSet};
+
+/// Wait before the first retry; it doubles after every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// How often finished deliveries are written to the delivery log.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+const SIGNATURE_HEADER: &str = "x-rusty-signature";
+const EVENT_HEADER: &str = "x-rusty-event";
+const DELIVERY_HEADER: &str = "x-rusty-delivery";
+
+/// The JSON body sent to a webhook. `text` makes it readable as a Slack
+/// incoming-webhook message.
+#[derive(Serialize)]
+struct Payload<'a> {
+    id: &'a str,
+    webhook: &'a str,
+    text: String,
+    #[serde(flatten)]
+    event: &'a ChangeEvent,
+    timestamp: i64,
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body` keyed with `secret`, the
+/// same scheme as GitHub's `X-Hub-Signature-256`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+fn summary(event: &ChangeEvent) -> String {
+    let mut text = match &event.environment {
+        Some(env) => format!("{}/{}: {}", event.project, env, event.kind.name()),
+        None => format!("{}: {}", event.project, event.kind.name()),
+    };
+    if !event.keys.is_empty() {
+        text.push_str(&format!(" ({})", event.keys.join(", ")));
+    }
+    text
+}
+
+/// How many times to try each delivery, and for how long.
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    pub attempts: u32,
+    pub timeout: Duration,
+}
+
+impl Policy {
+    /// For the server, which retries in the background.
+    pub const BACKGROUND: Self = Self {
+        attempts: 5,
+        timeout: Duration::from_secs(10),
+    };
+    /// For commands, which wait for their deliveries before exiting and so
+    /// mustn't hang on an unreachable endpoint.
+    pub const COMMAND: Self = Self {
+        attempts: 1,
+        timeout: Duration::from_secs(3),
+    };
+    /// For `webhook test`: a single attempt, given as long as the server's.
+    pub const TEST: Self = Self {
+        attempts: 1,
+        timeout: Duration::from_secs(10),
+    };
+}
+
+/// Posts events to webhooks.
+#[derive(Clone)]
+pub struct Sender {
+    client: reqwest::Client,
+    store: JsonStore,
+    attempts: u32,
+}
+
+impl Sender {
+    pub fn new(store: JsonStore, policy: Policy) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(policy.timeout)
+            .user_agent(concat!("rusty/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("the HTTP client configuration is valid");
+        Self {
+            client,
+            store,
+            attempts: policy.attempts,
+        }
+    }
+
+    /// Sends `event` to `webhook`, retrying network errors, 429s and 5xxs
+    /// with exponential backoff as often as the policy allows. The caller
+    /// records the outcome with [`JsonStore::record_deliveries`].
+    pub async fn deliver(&self, webhook: &Webhook, event: &ChangeEvent) -> Delivery {
+        let id = uuid::Uuid::new_v4().to_string();
+        let payload = Payload {
+            id: &id,
+            webhook: &webhook.id,
+            text: summary(event),
+            event,
+            timestamp: chrono::Utc::now().timestamp(),
+        };
+        let body = serde_json::to_vec(&payload).expect("payloads serialize");
+        let signature = sign(&webhook.secret, &body);
+
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempts = 0;
+        let (status, error) = loop {
+            attempts += 1;
+            let response = self
+                .client
+                .post(&webhook.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .header(EVENT_HEADER, event.kind.name())
+                .header(DELIVERY_HEADER, &id)
+                .body(body.clone())
+                .send()
+                .await;
+            let (status, error) = match response {
+                Ok(response) => (Some(response.status()), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            let retry = status.is_none_or(|status| {
+                status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            });
+            if !retry || attempts >= self.attempts {
+                break (status, error);
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        };
+
+        Delivery {
+            id,
+            webhook: webhook.id.clone(),
+            event: event.kind.name().to_string(),
+            revision: event.revision,
+            attempts,
+            status: status.map(|status| status.as_u16()),
+            error,
+            success: status.is_some_and(|status| status.is_success()),
+            delivered_at: chrono::Utc::now(),
+        }
+    }
+
+    /// The webhooks of the event's project that want it.
+    async fn targets(&self, event: &ChangeEvent) -> Vec<Webhook> {
+        let webhooks = match event.kind {
+            ChangeKind::ProjectDeleted => event.webhooks.clone(),
+            _ => self
+                .store
+                .list_webhooks(&event.project)
+                .await
+                .unwrap_or_default(),
+        };
+        webhooks
+            .into_iter()
+            .filter(|webhook| webhook.wants(event))
+            .collect()
+    }
+
+    /// Starts a delivery to every webhook that wants the event.
+    async fn dispatch(&self, deliveries: &mut JoinSet<(String, Delivery)>, event: ChangeEvent) {
+        for webhook in self.targets(&event).await {
+            let sender = self.clone();
+            let event = event.clone();
+            deliveries.spawn(async move {
+                let delivery = sender.deliver(&webhook, &event).await;
+                (event.project, delivery)
+            });
+        }
+    }
+
+    async fn record(&self, deliveries: Vec<(String, Delivery)>) {
+        if deliveries.is_empty() {
+            return;
+        }
+        if let Err(e) = self.store.record_deliveries(deliveries).await {
+            eprintln!("Failed to record webhook deliveries: {}", e);
+        }
+    }
+}
+
+/// Sends webhooks for every change saved through a store, in the
+/// background.
+pub struct Dispatcher {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl Dispatcher {
+    /// Starts delivering changes saved through `store` from now on.
+    /// Finished deliveries are written to the log in batches, so a burst
+    /// of them doesn't rewrite the database once each.
+    pub fn start(store: &JsonStore, policy: Policy) -> Self {
+        let mut events = store.subscribe();
+        let sender = Sender::new(store.clone(), policy);
+        let (shutdown, mut stop) = oneshot::channel::<()>();
+
+        let task = tokio::spawn(async move {
+            let mut deliveries = JoinSet::new();
+            let mut finished = Vec::new();
+            let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) => sender.dispatch(&mut deliveries, event).await,
+                        Err(RecvError::Lagged(skipped)) => {
+                            eprintln!("Webhooks missed {} changes", skipped)
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = &mut stop => {
+                        // Changes saved before the shutdown are still sent
+                        loop {
+                            match events.try_recv() {
+                                Ok(event) => sender.dispatch(&mut deliveries, event).await,
+                                Err(TryRecvError::Lagged(_)) => continue,
+                                Err(_) => break,
+                            }
+                        }
+                        break;
+                    }
+                    Some(done) = deliveries.join_next(), if !deliveries.is_empty() => {
+                        finished.extend(done);
+                    }
+                    _ = flush.tick(), if !finished.is_empty() => {
+                        sender.record(std::mem::take(&mut finished)).await;
+                    }
+                }
+            }
+            while let Some(done) = deliveries.join_next().await {
+                finished.extend(done);
+            }
+            sender.record(finished).await;
+        });
+
+        Self { shutdown, task }
+    }
+
+    /// Stops listening and waits for deliveries in flight, retries included.
+    pub async fn finish(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_like_github() {
+        // Example from GitHub's "Validating webhook deliveries" docs
+        assert_eq!(
+            sign("It's a Secret to Everybody", b"Hello, World!"),
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        );
+    }
+
+    #[test]
+    fn summaries_name_the_change() {
+        let event = ChangeEvent {
+            revision: 7,
+            kind: ChangeKind::VariablesChanged,
+            project: "app".to_string(),
+            environment: Some("production".to_string()),
+            keys: vec!["A".to_string(), "B".to_string()],
+            webhooks: Vec::new(),
+        };
+        assert_eq!(summary(&event), "app/production: variables.changed (A, B)");
+    }
+
+    #[tokio::test]
+    async fn deleted_projects_still_notify_their_webhooks() {
+        let path = std::env::temp_dir().join(format!("rusty-{}.json", uuid::Uuid::new_v4()));
+        let store = JsonStore::new(path.clone()).unwrap();
+        store.create_project("app".to_string(), None).await.unwrap();
+        let webhook = Webhook::new(
+            "https://hooks.example.com/x".to_string(),
+            None,
+            vec!["project.deleted".to_string()],
+            Vec::new(),
+        )
+        .unwrap();
+        store.add_webhook("app", webhook.clone()).await.unwrap();
+
+        let mut events = store.subscribe();
+        store.delete_project("app").await.unwrap();
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.kind, ChangeKind::ProjectDeleted);
+        assert_eq!(
+            Sender::new(store, Policy::BACKGROUND).targets(&event).await,
+            vec![webhook]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}